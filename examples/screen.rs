@@ -29,6 +29,7 @@ async fn main() -> Result<(), SerialError> {
     connection
         .execute_command(ScreenCapture)
         .await?
+        .to_image()
         .save("screencap.png")
         .unwrap();
 
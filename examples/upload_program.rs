@@ -8,6 +8,7 @@ use vex_v5_serial::{
     },
     packets::{
         file::FileExitAction,
+        program::SlotIcon,
         radio::{
             RadioChannel, SelectRadioChannelPacket, SelectRadioChannelPayload,
             SelectRadioChannelReplyPacket,
@@ -56,7 +57,7 @@ async fn main() -> Result<(), SerialError> {
         .execute_command(UploadProgram {
             name: "quick".to_string(),
             description: "A basic vexide program".to_string(),
-            icon: "USER029x.bmp".to_string(),
+            icon: SlotIcon::AlienInUfo,
             program_type: "vexide".to_string(),
             slot: 4,
             data: ProgramData::Monolith(program_data),
@@ -66,6 +67,8 @@ async fn main() -> Result<(), SerialError> {
             cold_callback: Some(callback_generator("Cold")),
             hot_callback: Some(callback_generator("Hot")),
             monolith_callback: Some(callback_generator("Monolith")),
+            progress_callback: Some(callback_generator("Total")),
+            cancel: None,
         })
         .await?;
 
@@ -1,4 +1,4 @@
-use crate::decode::{Decode, DecodeError};
+use crate::decode::{Counted, Decode, DecodeError};
 
 pub struct Array<T> {
     data: Vec<T>,
@@ -16,10 +16,11 @@ impl<T: Decode> Array<T> {
         data: impl IntoIterator<Item = u8>,
         len: usize,
     ) -> Result<Self, DecodeError> {
-        let mut data = data.into_iter();
+        let mut data = Counted::new(data.into_iter());
         let mut vec = Vec::with_capacity(len);
-        for _ in 0..len {
-            vec.push(T::decode(&mut data)?);
+        for index in 0..len {
+            let start = data.position();
+            vec.push(T::decode(&mut data).map_err(|e| e.context(&format!("[{index}]"), start))?);
         }
         Ok(Self { data: vec })
     }
@@ -0,0 +1,72 @@
+use crate::{
+    connection::Connection,
+    packets::device::{DeviceType, GetDeviceStatusPacket, GetDeviceStatusReplyPacket},
+};
+
+use super::Command;
+
+/// The number of smart ports on a V5 brain.
+pub const SMART_PORT_COUNT: u8 = 21;
+
+/// What is plugged into one of the brain's ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    /// 1-indexed port number. Port 22 is the internal ADI expander and port 23 is the battery.
+    pub port: u8,
+    pub device_type: DeviceType,
+    /// Whether a device is plugged into the port.
+    pub connected: bool,
+    /// The device's firmware version, or 0 if nothing is connected.
+    pub version: u16,
+}
+
+/// Lists the devices on every smart port and the brain's internal devices.
+///
+/// The brain only reports ports that have something plugged in, so empty smart ports are
+/// filled in as [`DeviceType::NoSensor`]. The result is sorted by port.
+#[derive(Debug, Clone, Copy)]
+pub struct GetDeviceList;
+impl Command for GetDeviceList {
+    type Output = Vec<DeviceInfo>;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetDeviceStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetDeviceStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        let mut devices = status
+            .devices
+            .into_inner()
+            .into_iter()
+            .map(|device| DeviceInfo {
+                port: device.port,
+                device_type: device.device_type,
+                connected: device.device_type != DeviceType::NoSensor,
+                version: device.version,
+            })
+            .collect::<Vec<_>>();
+
+        for port in 1..=SMART_PORT_COUNT {
+            if !devices.iter().any(|device| device.port == port) {
+                devices.push(DeviceInfo {
+                    port,
+                    device_type: DeviceType::NoSensor,
+                    connected: false,
+                    version: 0,
+                });
+            }
+        }
+        devices.sort_by_key(|device| device.port);
+
+        Ok(devices)
+    }
+}
@@ -0,0 +1,77 @@
+//! Commands for the brain's factory mode.
+//!
+//! Factory mode unlocks operations meant for VEX's own tooling, such as flashing firmware.
+//! Misusing them can leave a brain unable to boot, so enabling it requires a
+//! [`FactoryConfirmation`].
+
+use crate::{
+    connection::Connection,
+    packets::factory::{
+        FactoryEnablePacket, FactoryEnablePayload, FactoryEnableReplyPacket, FactoryStatus,
+        GetFactoryStatusPacket, GetFactoryStatusReplyPacket,
+    },
+};
+
+use super::{Command, CommandError};
+
+/// Reads the brain's factory mode status without changing it.
+#[derive(Debug, Clone, Copy)]
+pub struct GetFactoryStatus;
+impl Command for GetFactoryStatus {
+    type Output = FactoryStatus;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        Ok(connection
+            .packet_handshake::<GetFactoryStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetFactoryStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?)
+    }
+}
+
+/// Proof that the caller means to put the brain into factory mode.
+///
+/// This can only be made with [`FactoryConfirmation::accept_risk_of_bricking`], so that
+/// enabling factory mode never happens by accident.
+#[derive(Debug)]
+pub struct FactoryConfirmation(());
+impl FactoryConfirmation {
+    /// Acknowledges that factory mode operations can leave the brain unable to boot.
+    pub fn accept_risk_of_bricking() -> Self {
+        Self(())
+    }
+}
+
+/// Puts the brain into factory mode.
+///
+/// Fails with [`CommandError::FactoryModeRefused`] if the firmware won't allow it.
+#[derive(Debug)]
+pub struct EnableFactoryMode {
+    pub confirm: FactoryConfirmation,
+}
+impl Command for EnableFactoryMode {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        connection
+            .packet_handshake::<FactoryEnableReplyPacket>(
+                connection.timeout(),
+                5,
+                FactoryEnablePacket::new(FactoryEnablePayload::new()),
+            )
+            .await?
+            .try_into_inner()
+            .map_err(CommandError::FactoryModeRefused)?;
+
+        Ok(())
+    }
+}
@@ -1,31 +1,55 @@
-use std::{io::Write, time::Duration};
+use std::{
+    borrow::Cow,
+    io::Write,
+    time::{Duration, SystemTime},
+};
 
 use flate2::{Compression, GzBuilder};
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "bluetooth")]
 use crate::connection::bluetooth::BluetoothConnection;
 use crate::{
     connection::{Connection, ConnectionType},
-    crc::VEX_CRC32,
+    crc::crc32,
+    decode::DecodeError,
+    encode::{Encode, EncodeError},
+    packets::cdc2::Cdc2Ack,
     packets::file::{
-        ExitFileTransferPacket, ExitFileTransferReplyPacket, FileDownloadTarget, FileExitAction,
-        FileInitAction, FileInitOption, FileVendor, InitFileTransferPacket,
+        EraseFilePacket, EraseFilePayload, EraseFileReplyPacket, ExitFileTransferPacket,
+        ExitFileTransferReplyPacket, FileDownloadTarget, FileExitAction, FileFormatConfirmation,
+        FileFormatPacket, FileFormatReplyPacket, FileInitAction, FileInitOption, FileVendor,
+        GetDirectoryEntryPacket, GetDirectoryEntryPayload, GetDirectoryEntryReplyPacket,
+        GetDirectoryEntryReplyPayload, GetDirectoryFileCountPacket, GetDirectoryFileCountPayload,
+        GetDirectoryFileCountReplyPacket, GetFileMetadataPacket, GetFileMetadataPayload,
+        GetFileMetadataReplyPacket, GetFileMetadataReplyPayload, InitFileTransferPacket,
         InitFileTransferPayload, InitFileTransferReplyPacket, LinkFilePacket, LinkFilePayload,
         LinkFileReplyPacket, ReadFilePacket, ReadFilePayload, ReadFileReplyPacket, WriteFilePacket,
         WriteFilePayload, WriteFileReplyPacket,
     },
+    packets::program::SlotIcon,
     string::FixedLengthString,
-    timestamp::j2000_timestamp,
+    timestamp::{j2000_timestamp, system_time_from_j2000},
     version::Version,
 };
 
-use super::Command;
+use super::{CancellationToken, Command, CommandError};
 
 pub const COLD_START: u32 = 0x3800000;
 const USER_PROGRAM_CHUNK_SIZE: u16 = 4096;
+/// How long to wait for the brain to finish formatting its storage.
+pub const FORMAT_TIMEOUT: Duration = Duration::from_secs(15);
+/// How many times to ask for a chunk when the brain replies with a different one.
+const MAX_CHUNK_ATTEMPTS: usize = 5;
 
+/// Reads a file off of the brain.
+///
+/// The brain reports the file's length when the transfer starts, and exactly that many
+/// bytes are returned. `size` is only a hint for the brain and does not limit the read.
+///
+/// Every chunk the brain sends back is checked to be the one that was asked for, and asked
+/// for again if it isn't, so a late or out of order reply can't corrupt the file.
 pub struct DownloadFile {
     pub filename: FixedLengthString<23>,
     pub filetype: FixedLengthString<3>,
@@ -33,8 +57,16 @@ pub struct DownloadFile {
     pub vendor: FileVendor,
     pub target: Option<FileDownloadTarget>,
     pub load_addr: u32,
+    /// The most bytes to read per packet, or `None` to pick a size for the connection.
+    ///
+    /// This is rounded down to a multiple of 4 and capped at what the brain accepts.
+    pub chunk_size: Option<u16>,
 
-    pub progress_callback: Option<Box<dyn FnMut(f32) + Send>>,
+    /// Called with the number of bytes downloaded so far and the file's length after each
+    /// chunk arrives, the same way as [`UploadFile::progress_callback`].
+    pub progress_callback: Option<Box<dyn FnMut(u32, u32) + Send>>,
+    /// Stops the download between chunks when cancelled.
+    pub cancel: Option<CancellationToken>,
 }
 impl Command for DownloadFile {
     type Output = Vec<u8>;
@@ -47,7 +79,7 @@ impl Command for DownloadFile {
 
         let transfer_response = connection
             .packet_handshake::<InitFileTransferReplyPacket>(
-                Duration::from_millis(500),
+                connection.timeout(),
                 5,
                 InitFileTransferPacket::new(InitFileTransferPayload {
                     operation: FileInitAction::Read,
@@ -71,45 +103,268 @@ impl Command for DownloadFile {
             .await?;
         let transfer_response = transfer_response.try_into_inner()?;
 
-        let max_chunk_size = if transfer_response.window_size > 0
-            && transfer_response.window_size <= USER_PROGRAM_CHUNK_SIZE
-        {
-            transfer_response.window_size
-        } else {
-            USER_PROGRAM_CHUNK_SIZE
-        };
+        let max_chunk_size = chunk_size(
+            connection.connection_type(),
+            transfer_response.window_size,
+            self.chunk_size,
+        );
+        let file_size = transfer_response.file_size;
 
-        let mut data = Vec::with_capacity(transfer_response.file_size as usize);
-        let mut offset = 0;
-        loop {
-            let read = connection
-                .packet_handshake::<ReadFileReplyPacket>(
-                    Duration::from_millis(500),
-                    5,
-                    ReadFilePacket::new(ReadFilePayload {
-                        address: self.load_addr + offset,
-                        size: max_chunk_size,
-                    }),
-                )
-                .await?;
-            let read = read.payload.unwrap()?;
-            let chunk_data = read.1.into_inner();
-            offset += chunk_data.len() as u32;
-            let last = transfer_response.file_size <= offset;
-            let progress = (offset as f32 / transfer_response.file_size as f32) * 100.0;
-            data.extend(chunk_data);
+        // The file size comes from the brain, so only reserve room for one chunk up front.
+        let mut data = Vec::with_capacity(file_size.min(max_chunk_size as u32) as usize);
+        if file_size == 0 {
             if let Some(callback) = &mut self.progress_callback {
-                callback(progress);
+                callback(0, 0);
+            }
+        }
+        while (data.len() as u32) < file_size {
+            if is_cancelled(&self.cancel) {
+                return cancel_transfer(connection).await;
             }
-            if last {
-                break;
+
+            let offset = data.len() as u32;
+            // Reads must be 4-byte aligned, so the last one may overshoot the end of the file.
+            let remaining = (file_size - offset).next_multiple_of(4);
+            let size = remaining.min(max_chunk_size as u32) as u16;
+
+            let address = self.load_addr + offset;
+            let mut attempts = 0;
+            let chunk_data = loop {
+                let read = connection
+                    .packet_handshake::<ReadFileReplyPacket>(
+                        connection.timeout(),
+                        5,
+                        ReadFilePacket::new(ReadFilePayload { address, size }),
+                    )
+                    .await?;
+                let (reply_address, chunk_data) = read.payload.unwrap()?;
+                if reply_address == address {
+                    break chunk_data.into_inner();
+                }
+
+                // A late reply to an earlier read would put its data in the wrong place.
+                attempts += 1;
+                if attempts == MAX_CHUNK_ATTEMPTS {
+                    return Err(CommandError::ChunkMismatch {
+                        expected: address,
+                        found: reply_address,
+                    }
+                    .into());
+                }
+                warn!(
+                    "Expected chunk at {:#x}, got one at {:#x}. Requesting it again",
+                    address, reply_address
+                );
+            };
+            if chunk_data.is_empty() {
+                // The brain has nothing more to give, so don't ask forever.
+                return Err(DecodeError::PacketTooShort.into());
+            }
+            data.extend(chunk_data);
+
+            if let Some(callback) = &mut self.progress_callback {
+                // The last chunk may be padded past the end of the file
+                callback((data.len() as u32).min(file_size), file_size);
             }
         }
+        // Drop the padding from the last aligned read.
+        data.truncate(file_size as usize);
+
+        connection
+            .packet_handshake::<ExitFileTransferReplyPacket>(
+                connection.timeout(),
+                5,
+                ExitFileTransferPacket::new(FileExitAction::DoNothing),
+            )
+            .await?
+            .try_into_inner()?;
 
         Ok(data)
     }
 }
 
+/// Information about a file stored on the brain.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileMetadata {
+    pub name: String,
+    pub size: u32,
+    /// The file's extension, such as `bin` or `ini`.
+    pub file_type: String,
+    /// When the file was uploaded, according to the host that uploaded it.
+    pub timestamp: SystemTime,
+    pub version: Version,
+    /// The storage entry address of the file.
+    pub load_address: u32,
+    pub crc: u32,
+    /// The program slot this file belongs to, if it was uploaded as part of a program.
+    ///
+    /// This uses the same numbering as [`UploadProgram::slot`].
+    pub slot: Option<u8>,
+}
+impl From<GetDirectoryEntryReplyPayload> for FileMetadata {
+    fn from(entry: GetDirectoryEntryReplyPayload) -> Self {
+        let name = entry.file_name.to_string();
+        Self {
+            slot: program_slot(&name),
+            name,
+            size: entry.size,
+            file_type: entry.file_type.to_string(),
+            timestamp: system_time_from_j2000(entry.timestamp),
+            version: entry.version,
+            load_address: entry.load_address,
+            crc: entry.crc,
+        }
+    }
+}
+
+/// Parses the slot out of a program file name such as `slot1.bin`, `slot1.ini` or `slot1_lib.bin`.
+fn program_slot(file_name: &str) -> Option<u8> {
+    let rest = file_name.strip_prefix("slot")?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(rest, |end| &rest[..end]);
+    digits.parse().ok()
+}
+
+/// Lists every file stored on the brain under a vendor.
+///
+/// Each file is asked for by a one-byte index, so only the first 256 files can be listed.
+pub struct ListFiles {
+    pub vendor: FileVendor,
+}
+impl Command for ListFiles {
+    type Output = Vec<FileMetadata>;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let count = connection
+            .packet_handshake::<GetDirectoryFileCountReplyPacket>(
+                connection.timeout(),
+                5,
+                GetDirectoryFileCountPacket::new(GetDirectoryFileCountPayload {
+                    vendor: self.vendor,
+                    option: 0,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        // The brain only hands out entries one index at a time, and the index is a single byte.
+        let readable = count.min(u8::MAX as u16 + 1);
+        if readable < count {
+            warn!(
+                "The brain has {} files, but only the first {} can be listed",
+                count, readable
+            );
+        }
+
+        let mut files = Vec::with_capacity(readable as usize);
+        for file_index in (0..=u8::MAX).take(readable as usize) {
+            let entry = connection
+                .packet_handshake::<GetDirectoryEntryReplyPacket>(
+                    connection.timeout(),
+                    5,
+                    GetDirectoryEntryPacket::new(GetDirectoryEntryPayload {
+                        file_index,
+                        unknown: 0,
+                    }),
+                )
+                .await?
+                .try_into_inner()?;
+
+            match entry {
+                Some(entry) => files.push(entry.into()),
+                None => trace!("No directory entry at index {}", file_index),
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Asks the brain for a file's metadata, returning `None` if the file doesn't exist.
+pub(crate) async fn file_metadata<C: Connection + ?Sized>(
+    connection: &mut C,
+    vendor: FileVendor,
+    file_name: &FixedLengthString<23>,
+) -> Result<Option<GetFileMetadataReplyPayload>, C::Error> {
+    Ok(connection
+        .packet_handshake::<GetFileMetadataReplyPacket>(
+            connection.timeout(),
+            5,
+            GetFileMetadataPacket::new(GetFileMetadataPayload {
+                vendor,
+                option: 0,
+                file_name: file_name.clone(),
+            }),
+        )
+        .await?
+        .try_into_inner()?)
+}
+
+/// Deletes a file from the brain.
+///
+/// Fails with [`CommandError::FileNotFound`] if the brain has no such file, rather than
+/// with whatever NACK the brain would send back for erasing it.
+pub struct DeleteFile {
+    pub filename: FixedLengthString<23>,
+    pub vendor: FileVendor,
+}
+impl Command for DeleteFile {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        // The brain has no dedicated NACK for a missing file, so check that it exists first.
+        if file_metadata(connection, self.vendor, &self.filename)
+            .await?
+            .is_none()
+        {
+            return Err(CommandError::FileNotFound(self.filename.to_string()).into());
+        }
+
+        connection
+            .packet_handshake::<EraseFileReplyPacket>(
+                connection.timeout(),
+                5,
+                EraseFilePacket::new(EraseFilePayload {
+                    vendor: self.vendor,
+                    option: 128,
+                    file_name: self.filename.clone(),
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        connection
+            .packet_handshake::<ExitFileTransferReplyPacket>(
+                connection.timeout(),
+                5,
+                ExitFileTransferPacket::new(FileExitAction::DoNothing),
+            )
+            .await?
+            .try_into_inner()?;
+
+        info!("Deleted file: {}", self.filename);
+        Ok(())
+    }
+}
+
+/// Picks how many bytes to transfer per packet, preferring `requested` if the brain allows it.
+fn chunk_size(con_type: ConnectionType, window_size: u16, requested: Option<u16>) -> u16 {
+    let max_chunk_size = max_chunk_size(con_type, window_size);
+    match requested {
+        // Chunks have to stay 4-byte aligned.
+        Some(requested) => (requested.min(max_chunk_size) & !3).max(4),
+        None => max_chunk_size,
+    }
+}
+
 #[cfg(feature = "bluetooth")]
 fn max_chunk_size(con_type: ConnectionType, window_size: u16) -> u16 {
     if con_type.is_bluetooth() {
@@ -131,11 +386,79 @@ fn max_chunk_size(_con_type: ConnectionType, window_size: u16) -> u16 {
     }
 }
 
+/// A file already on the brain that an uploaded file depends on.
+///
+/// The brain loads a linked file along with the file that links to it, which is how a hot
+/// program finds its cold library. Any uploaded file can link to a library, including one
+/// shared between several programs.
+#[derive(Debug, Clone)]
 pub struct LinkedFile {
     pub filename: FixedLengthString<23>,
+    /// The vendor the linked file was uploaded with, or `None` for [`FileVendor::User`].
     pub vendor: Option<FileVendor>,
 }
+impl LinkedFile {
+    /// Links to a user file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncodeError::StringTooLong`] if the name is longer than 23 bytes.
+    pub fn new(filename: impl Into<String>) -> Result<Self, EncodeError> {
+        Ok(Self {
+            filename: FixedLengthString::new(filename.into())?,
+            vendor: None,
+        })
+    }
+}
+
+/// Proof that the caller means to erase every file on the brain.
+///
+/// This can only be made with [`FormatConfirmation::erase_all_files`], so that
+/// formatting never happens by accident.
+#[derive(Debug)]
+pub struct FormatConfirmation(());
+impl FormatConfirmation {
+    /// Acknowledges that every program and file on the brain will be lost.
+    pub fn erase_all_files() -> Self {
+        Self(())
+    }
+}
+
+/// Erases all of the brain's user storage at once, including every program slot.
+///
+/// Formatting takes several seconds, so this waits for up to [`FORMAT_TIMEOUT`] rather
+/// than the connection's usual timeout.
+#[derive(Debug)]
+pub struct FormatUserStorage {
+    pub confirm: FormatConfirmation,
+}
+impl Command for FormatUserStorage {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        info!("Formatting user storage");
+        connection
+            .packet_handshake::<FileFormatReplyPacket>(
+                FORMAT_TIMEOUT.max(connection.timeout()),
+                1,
+                FileFormatPacket::new(FileFormatConfirmation::new()),
+            )
+            .await?
+            .try_into_inner()?;
 
+        info!("Formatted user storage");
+        Ok(())
+    }
+}
+
+/// Uploads a file to the brain.
+///
+/// The CRC32 of the uploaded data is sent when the transfer starts, and the brain checks the
+/// file it received against it once the transfer is finished. If they differ, this fails with
+/// [`CommandError::CrcMismatch`].
 pub struct UploadFile<'a> {
     pub filename: FixedLengthString<23>,
     pub filetype: FixedLengthString<3>,
@@ -145,31 +468,73 @@ pub struct UploadFile<'a> {
     pub load_addr: u32,
     pub linked_file: Option<LinkedFile>,
     pub after_upload: FileExitAction,
+    /// The offset into `data` to start writing from, or into the compressed data if `compress`
+    /// is set.
+    ///
+    /// This is advanced as each chunk is acknowledged, so if an upload fails partway
+    /// through, executing the same command again continues the transfer the brain still has
+    /// open from where it left off. If the brain no longer has it open, such as after it was
+    /// restarted, the upload starts over from the beginning.
+    /// Over Bluetooth, chunks aren't acknowledged, so this only tracks what was sent.
+    pub offset: u32,
+    /// Whether to gzip `data` before uploading it.
+    ///
+    /// `data` itself is left as it is, and compressed again each time the command is
+    /// executed. If compressing doesn't make it any smaller, it is uploaded as is.
+    ///
+    /// The file-init packet has no flag for compression, because the brain recognizes a
+    /// gzipped file by its header and decompresses it by itself.
+    pub compress: bool,
+    /// The most bytes to write per packet, or `None` to pick a size for the connection.
+    ///
+    /// This is rounded down to a multiple of 4 and capped at what the brain accepts. Once the
+    /// transfer has started, this is set to the size being used, which a resumed upload
+    /// needs to carry on with.
+    pub chunk_size: Option<u16>,
 
-    pub progress_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
+    /// Called with the number of bytes uploaded so far and the total number of bytes after
+    /// each chunk is acknowledged.
+    ///
+    /// If `data` is compressed, these count the compressed bytes. The callback is always
+    /// called once the whole file is written, even if there was nothing left to write.
+    pub progress_callback: Option<Box<dyn FnMut(u32, u32) + Send + 'a>>,
+    /// Stops the upload between chunks when cancelled.
+    ///
+    /// Cancelling closes the transfer on the brain, so executing the command again starts
+    /// the upload over.
+    pub cancel: Option<CancellationToken>,
 }
-impl Command for UploadFile<'_> {
-    type Output = ();
-    async fn execute<C: Connection + ?Sized>(
-        &mut self,
-        connection: &mut C,
-    ) -> Result<Self::Output, C::Error> {
-        info!("Uploading file: {}", self.filename);
-        let vendor = self.vendor.unwrap_or(FileVendor::User);
-        let target = self.target.unwrap_or(FileDownloadTarget::Qspi);
-
-        let crc = VEX_CRC32.checksum(&self.data);
+impl UploadFile<'_> {
+    /// Continues an upload from the given offset into `data`, rather than from the start.
+    ///
+    /// The offset should be one previously reached by [`UploadFile::offset`], along with the
+    /// [`UploadFile::chunk_size`] the upload was using. Without a chunk size, the upload
+    /// starts over.
+    pub fn resume(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
 
+    /// Opens a new transfer on the brain for `size` bytes with the given CRC32, returning
+    /// its window size.
+    async fn start_transfer<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+        vendor: FileVendor,
+        target: FileDownloadTarget,
+        size: u32,
+        crc: u32,
+    ) -> Result<u16, C::Error> {
         let transfer_response = connection
             .packet_handshake::<InitFileTransferReplyPacket>(
-                Duration::from_millis(500),
+                connection.timeout(),
                 5,
                 InitFileTransferPacket::new(InitFileTransferPayload {
                     operation: FileInitAction::Write,
                     target,
                     vendor,
                     options: FileInitOption::Overwrite,
-                    write_file_size: self.data.len() as u32,
+                    write_file_size: size,
                     load_address: self.load_addr,
                     write_file_crc: crc,
                     file_extension: self.filetype.clone(),
@@ -190,7 +555,7 @@ impl Command for UploadFile<'_> {
         if let Some(linked_file) = &self.linked_file {
             connection
                 .packet_handshake::<LinkFileReplyPacket>(
-                    Duration::from_millis(500),
+                    connection.timeout(),
                     5,
                     LinkFilePacket::new(LinkFilePayload {
                         vendor: linked_file.vendor.unwrap_or(FileVendor::User),
@@ -202,64 +567,160 @@ impl Command for UploadFile<'_> {
                 .try_into_inner()?;
         }
 
-        let window_size = transfer_response.window_size;
-
-        // The maximum packet size is 244 bytes for bluetooth
-        let max_chunk_size = max_chunk_size(connection.connection_type(), window_size);
+        Ok(transfer_response.window_size)
+    }
+}
+impl Command for UploadFile<'_> {
+    type Output = ();
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        info!("Uploading file: {}", self.filename);
+        let vendor = self.vendor.unwrap_or(FileVendor::User);
+        let target = self.target.unwrap_or(FileDownloadTarget::Qspi);
 
-        debug!("max_chunk_size: {}", max_chunk_size);
+        // Compression is deterministic, so offsets reached by an earlier attempt still line up.
+        let data = if self.compress {
+            debug!("Compressing {}", self.filename);
+            let compressed = gzip(&self.data);
+            if compressed.len() < self.data.len() {
+                Cow::Owned(compressed)
+            } else {
+                debug!(
+                    "Compression didn't shrink {}, uploading it as is",
+                    self.filename
+                );
+                Cow::Borrowed(&self.data[..])
+            }
+        } else {
+            Cow::Borrowed(&self.data[..])
+        };
+        let crc = crc32(&data);
 
-        let mut offset = 0;
-        for chunk in self.data.chunks(max_chunk_size as _) {
-            let chunk = if chunk.len() < max_chunk_size as _ && chunk.len() % 4 != 0 {
-                let mut new_chunk = Vec::new();
-                new_chunk.extend_from_slice(chunk);
-                new_chunk.resize(chunk.len() + (4 - chunk.len() % 4), 0);
-                new_chunk
+        // A new transfer starts with an empty file, so resuming has to carry on with the one
+        // the brain already has open.
+        let mut resuming = self.offset != 0 && self.chunk_size.is_some();
+        'transfer: loop {
+            if resuming {
+                info!("Resuming upload at offset {}", self.offset);
             } else {
-                chunk.to_vec()
-            };
-            trace!("sending chunk of size: {}", chunk.len());
-            let progress = (offset as f32 / self.data.len() as f32) * 100.0;
-            if let Some(callback) = &mut self.progress_callback {
-                callback(progress);
+                self.offset = 0;
+                let window_size = self
+                    .start_transfer(connection, vendor, target, data.len() as u32, crc)
+                    .await?;
+
+                // The maximum packet size is 244 bytes for bluetooth
+                self.chunk_size = Some(chunk_size(
+                    connection.connection_type(),
+                    window_size,
+                    self.chunk_size,
+                ));
             }
+            let max_chunk_size = self.chunk_size.unwrap_or_default();
+            debug!("max_chunk_size: {}", max_chunk_size);
 
-            let packet = WriteFilePacket::new(WriteFilePayload {
-                address: (self.load_addr + offset) as _,
-                chunk_data: chunk.clone(),
-            });
+            let total = data.len() as u32;
+            let remaining = data.get(self.offset as usize..).unwrap_or_default();
+            if remaining.is_empty() {
+                if let Some(callback) = &mut self.progress_callback {
+                    callback(total, total);
+                }
+            }
+            for chunk in remaining.chunks(max_chunk_size as _) {
+                if is_cancelled(&self.cancel) {
+                    self.offset = 0;
+                    return cancel_transfer(connection).await;
+                }
 
-            // On bluetooth, we dont wait for the reply
-            if connection.connection_type() == ConnectionType::Bluetooth {
-                connection.send_packet(packet).await?;
-            } else {
-                connection
-                    .packet_handshake::<WriteFileReplyPacket>(Duration::from_millis(500), 5, packet)
-                    .await?
-                    .try_into_inner()?;
+                let chunk = if chunk.len() < max_chunk_size as _ && chunk.len() % 4 != 0 {
+                    let mut new_chunk = Vec::new();
+                    new_chunk.extend_from_slice(chunk);
+                    new_chunk.resize(chunk.len() + (4 - chunk.len() % 4), 0);
+                    new_chunk
+                } else {
+                    chunk.to_vec()
+                };
+                trace!("sending chunk of size: {}", chunk.len());
+
+                let packet = WriteFilePacket::new(WriteFilePayload {
+                    address: (self.load_addr + self.offset) as _,
+                    chunk_data: chunk.clone(),
+                });
+
+                // On bluetooth, we dont wait for the reply
+                if connection.connection_type() == ConnectionType::Bluetooth {
+                    connection.send_packet(packet).await?;
+                } else {
+                    let reply = connection
+                        .packet_handshake::<WriteFileReplyPacket>(connection.timeout(), 5, packet)
+                        .await?;
+                    match reply.try_into_inner() {
+                        Err(Cdc2Ack::NackUninitializedTransfer) if resuming => {
+                            warn!("The brain is no longer expecting this upload, starting it over");
+                            resuming = false;
+                            continue 'transfer;
+                        }
+                        result => result?,
+                    }
+                }
+
+                self.offset += chunk.len() as u32;
+
+                if let Some(callback) = &mut self.progress_callback {
+                    // The last chunk may be padded past the end of the data
+                    callback(self.offset.min(total), total);
+                }
             }
 
-            offset += chunk.len() as u32;
-        }
-        if let Some(callback) = &mut self.progress_callback {
-            callback(100.0);
+            break;
         }
 
-        connection
+        let exit = connection
             .packet_handshake::<ExitFileTransferReplyPacket>(
-                Duration::from_millis(800),
+                connection.timeout() * 2,
                 5,
                 ExitFileTransferPacket::new(self.after_upload),
             )
-            .await?
-            .try_into_inner()?;
+            .await?;
+        match exit.try_into_inner() {
+            Err(Cdc2Ack::NackProgramCrc) => {
+                return Err(CommandError::CrcMismatch { expected: crc }.into())
+            }
+            result => result?,
+        }
 
         info!("Successfully uploaded file: {}", self.filename);
         Ok(())
     }
 }
 
+fn is_cancelled(cancel: &Option<CancellationToken>) -> bool {
+    cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+}
+
+/// Ends a file transfer early so the brain isn't left waiting for the rest of it.
+async fn cancel_transfer<C: Connection + ?Sized, T>(connection: &mut C) -> Result<T, C::Error> {
+    info!("Cancelling file transfer");
+    connection
+        .packet_handshake::<ExitFileTransferReplyPacket>(
+            connection.timeout(),
+            5,
+            ExitFileTransferPacket::new(FileExitAction::DoNothing),
+        )
+        .await?
+        .try_into_inner()?;
+
+    Err(CommandError::Cancelled.into())
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzBuilder::new().write(Vec::new(), Compression::default());
+    // Writing to a Vec can't fail
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ProgramData {
     #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
@@ -273,31 +734,169 @@ pub enum ProgramData {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Program {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
     pub name: String,
     pub slot: u8,
     pub icon: String,
     pub iconalt: String,
     pub description: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub date: String,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
-    // version: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
     pub ide: String,
     // file: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProgramIniConfig {
     pub project: Project,
     pub program: Program,
 }
 
+/// Builds the ini file that describes a program to the brain's program menu.
+///
+/// This is uploaded as `slot{N}.ini` alongside the program's binary, which
+/// [`UploadProgram`] does on its own. Encoding it gives the file's contents, ready to be
+/// used as [`UploadFile::data`].
+///
+/// ```
+/// use vex_v5_serial::{commands::file::ProgramIni, encode::Encode, packets::program::SlotIcon};
+///
+/// let ini = ProgramIni::new("quick", 0)
+///     .description("A basic vexide program")
+///     .icon(SlotIcon::AlienInUfo)
+///     .ide("vexide")
+///     .encode()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgramIni(ProgramIniConfig);
+impl ProgramIni {
+    /// Creates the ini for a program in a 0-indexed slot, with no description and the
+    /// default icon.
+    pub fn new(name: impl Into<String>, slot: u8) -> Self {
+        Self(ProgramIniConfig {
+            project: Project {
+                version: String::new(),
+                ide: String::new(),
+            },
+            program: Program {
+                version: String::new(),
+                name: name.into(),
+                slot,
+                icon: SlotIcon::VexCodingStudio.file_name(),
+                iconalt: String::new(),
+                description: String::new(),
+                date: String::new(),
+            },
+        })
+    }
+
+    /// Sets the description shown when the program is selected.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.0.program.description = description.into();
+        self
+    }
+
+    /// Sets the icon shown next to the program.
+    pub fn icon(mut self, icon: SlotIcon) -> Self {
+        self.0.program.icon = icon.file_name();
+        self
+    }
+
+    /// Sets the program's own version, such as `1.0.0`.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.0.program.version = version.into();
+        self
+    }
+
+    /// Sets the name of the tool that built the program, such as `PROS` or `vexide`.
+    pub fn ide(mut self, ide: impl Into<String>) -> Self {
+        self.0.project.ide = ide.into();
+        self
+    }
+
+    /// Sets the version of the tool that built the program.
+    pub fn ide_version(mut self, version: impl Into<String>) -> Self {
+        self.0.project.version = version.into();
+        self
+    }
+
+    /// Sets when the program was built, conventionally as an ISO 8601 date and time.
+    pub fn date(mut self, date: impl Into<String>) -> Self {
+        self.0.program.date = date.into();
+        self
+    }
+
+    /// Returns the ini's sections as they will be written.
+    pub fn config(&self) -> &ProgramIniConfig {
+        &self.0
+    }
+}
+impl Encode for ProgramIni {
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        // Every field is a plain string or integer, which always serializes.
+        buf.extend(serde_ini::to_vec(&self.0).expect("Program ini failed to serialize"));
+        Ok(())
+    }
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(f32) + Send + 'a>;
+type UploadProgressCallback<'a> = Box<dyn FnMut(u32, u32) + Send + 'a>;
+
+/// Reports a file's progress to its own callback and the whole upload's to `overall`, both
+/// as percentages.
+///
+/// `before` is how many bytes of the upload came before this file, and `total` is how many
+/// bytes there are across every file.
+fn combined_progress<'a, 'b>(
+    mut file_callback: Option<ProgressCallback<'b>>,
+    overall: &'b mut Option<ProgressCallback<'a>>,
+    before: usize,
+    file_size: usize,
+    total: usize,
+) -> Option<UploadProgressCallback<'b>> {
+    if file_callback.is_none() && overall.is_none() {
+        return None;
+    }
+
+    Some(Box::new(move |sent, uploaded_size| {
+        // The file may have been compressed, so scale by what was actually uploaded.
+        let fraction = if uploaded_size == 0 {
+            1.0
+        } else {
+            sent as f32 / uploaded_size as f32
+        };
+        if let Some(callback) = &mut file_callback {
+            callback(fraction * 100.0);
+        }
+        if let Some(overall) = overall {
+            let uploaded = before as f32 + file_size as f32 * fraction;
+            overall(if total == 0 {
+                100.0
+            } else {
+                uploaded / total as f32 * 100.0
+            });
+        }
+    }))
+}
+
+/// Uploads a program to one of the brain's slots.
+///
+/// This uploads the slot's ini file first, followed by the program's binaries. A hot/cold
+/// program's cold library is uploaded as `slot{N}_lib.bin`, and its hot binary is linked to
+/// it so the brain loads both.
 pub struct UploadProgram<'a> {
     pub name: String,
     pub description: String,
-    pub icon: String,
+    pub icon: SlotIcon,
     pub program_type: String,
     /// 0-indexed slot
     pub slot: u8,
@@ -309,6 +908,10 @@ pub struct UploadProgram<'a> {
     pub cold_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
     pub hot_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
     pub monolith_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
+    /// Called with the progress of the whole upload, weighted by the size of each file.
+    pub progress_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
+    /// Stops the upload between chunks when cancelled.
+    pub cancel: Option<CancellationToken>,
 }
 impl Command for UploadProgram<'_> {
     type Output = ();
@@ -319,20 +922,22 @@ impl Command for UploadProgram<'_> {
     ) -> Result<Self::Output, C::Error> {
         let base_file_name = format!("slot{}", self.slot);
 
-        let ini = ProgramIniConfig {
-            program: Program {
-                description: self.description.clone(),
-                icon: self.icon.clone(),
-                iconalt: String::new(),
-                slot: self.slot,
-                name: self.name.clone(),
-            },
-            project: Project {
-                ide: self.program_type.clone(),
-            },
-        };
-        let ini = serde_ini::to_vec(&ini).unwrap();
+        let ini = ProgramIni::new(self.name.clone(), self.slot)
+            .description(self.description.clone())
+            .icon(self.icon)
+            .ide(self.program_type.clone())
+            .encode()?;
 
+        let total = ini.len()
+            + match &self.data {
+                ProgramData::Monolith(data) => data.len(),
+                ProgramData::HotCold { hot, cold } => {
+                    hot.as_ref().map_or(0, Vec::len) + cold.as_ref().map_or(0, Vec::len)
+                }
+            };
+        let mut uploaded = 0;
+
+        let ini_len = ini.len();
         let file_transfer = UploadFile {
             filename: FixedLengthString::new(format!("{}.ini", base_file_name))?,
             filetype: FixedLengthString::new("ini".to_string())?,
@@ -342,24 +947,25 @@ impl Command for UploadProgram<'_> {
             load_addr: COLD_START,
             linked_file: None,
             after_upload: FileExitAction::Halt,
-            progress_callback: self.ini_callback.take(),
+            offset: 0,
+            compress: false,
+            chunk_size: None,
+            progress_callback: combined_progress(
+                self.ini_callback.take(),
+                &mut self.progress_callback,
+                uploaded,
+                ini_len,
+                total,
+            ),
+            cancel: self.cancel.clone(),
         };
         connection.execute_command(file_transfer).await?;
+        uploaded += ini_len;
 
-        match &mut self.data {
+        match &self.data {
             ProgramData::Monolith(data) => {
                 info!("Uploading monolith binary");
 
-                // Compress the monolith program
-                // We don't need to change any other flags, the brain is smart enough to decompress it
-                if self.compress_program {
-                    debug!("Compressing monolith binary");
-                    let compressed = Vec::new();
-                    let mut encoder = GzBuilder::new().write(compressed, Compression::default());
-                    encoder.write_all(data).unwrap();
-                    *data = encoder.finish().unwrap();
-                }
-
                 connection
                     .execute_command(UploadFile {
                         filename: FixedLengthString::new(format!("{}.bin", base_file_name))?,
@@ -370,7 +976,17 @@ impl Command for UploadProgram<'_> {
                         load_addr: COLD_START,
                         linked_file: None,
                         after_upload: self.after_upload,
-                        progress_callback: self.monolith_callback.take(),
+                        offset: 0,
+                        compress: self.compress_program,
+                        chunk_size: None,
+                        progress_callback: combined_progress(
+                            self.monolith_callback.take(),
+                            &mut self.progress_callback,
+                            uploaded,
+                            data.len(),
+                            total,
+                        ),
+                        cancel: self.cancel.clone(),
                     })
                     .await?;
             }
@@ -383,20 +999,12 @@ impl Command for UploadProgram<'_> {
                         self.after_upload
                     };
 
-                    // Compress the cold program
-                    // We don't need to change any other flags, the brain is smart enough to decompress it
-                    if self.compress_program {
-                        debug!("Compressing cold binary");
-                        let compressed = Vec::new();
-                        let mut encoder =
-                            GzBuilder::new().write(compressed, Compression::default());
-                        encoder.write_all(cold).unwrap();
-                        *cold = encoder.finish().unwrap();
-                    }
-
                     connection
                         .execute_command(UploadFile {
-                            filename: FixedLengthString::new(format!("{}.bin", base_file_name))?,
+                            filename: FixedLengthString::new(format!(
+                                "{}_lib.bin",
+                                base_file_name
+                            ))?,
                             filetype: FixedLengthString::new("bin".to_string())?,
                             vendor: None,
                             data: cold.clone(),
@@ -404,25 +1012,25 @@ impl Command for UploadProgram<'_> {
                             load_addr: COLD_START,
                             linked_file: None,
                             after_upload,
-                            progress_callback: self.cold_callback.take(),
+                            offset: 0,
+                            compress: self.compress_program,
+                            chunk_size: None,
+                            progress_callback: combined_progress(
+                                self.cold_callback.take(),
+                                &mut self.progress_callback,
+                                uploaded,
+                                cold.len(),
+                                total,
+                            ),
+                            cancel: self.cancel.clone(),
                         })
                         .await?;
+                    uploaded += cold.len();
                 }
 
                 if let Some(hot) = hot {
                     info!("Uploading hot binary");
-                    let linked_file = Some(LinkedFile {
-                        filename: FixedLengthString::new(format!("{}_lib.bin", base_file_name))?,
-                        vendor: None,
-                    });
-                    if self.compress_program {
-                        debug!("Compressing hot binary");
-                        let compressed = Vec::new();
-                        let mut encoder =
-                            GzBuilder::new().write(compressed, Compression::default());
-                        encoder.write_all(hot).unwrap();
-                        *hot = encoder.finish().unwrap();
-                    }
+                    let linked_file = Some(LinkedFile::new(format!("{}_lib.bin", base_file_name))?);
 
                     connection
                         .execute_command(UploadFile {
@@ -434,7 +1042,17 @@ impl Command for UploadProgram<'_> {
                             load_addr: 0x07800000,
                             linked_file,
                             after_upload: self.after_upload,
-                            progress_callback: self.hot_callback.take(),
+                            offset: 0,
+                            compress: self.compress_program,
+                            chunk_size: None,
+                            progress_callback: combined_progress(
+                                self.hot_callback.take(),
+                                &mut self.progress_callback,
+                                uploaded,
+                                hot.len(),
+                                total,
+                            ),
+                            cancel: self.cancel.clone(),
                         })
                         .await?;
                 }
@@ -444,3 +1062,179 @@ impl Command for UploadProgram<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{chunk_size, combined_progress, program_slot, ProgressCallback, UploadFile};
+    use crate::{
+        commands::{Command, CommandError},
+        connection::{
+            mock::{MockConnection, MockError},
+            ConnectionType,
+        },
+        crc::crc32,
+        encode::Encode,
+        fixtures::cdc2_reply,
+        packets::file::{
+            ExitFileTransferPacket, FileExitAction, WriteFilePacket, WriteFilePayload,
+        },
+        string::FixedLengthString,
+    };
+
+    #[test]
+    fn program_slots() {
+        assert_eq!(program_slot("slot3.bin"), Some(3));
+        assert_eq!(program_slot("slot3_lib.bin"), Some(3));
+        assert_eq!(program_slot("slot12.ini"), Some(12));
+        assert_eq!(program_slot("data.txt"), None);
+        assert_eq!(program_slot("slot.bin"), None);
+    }
+
+    #[test]
+    fn chunk_sizes() {
+        assert_eq!(chunk_size(ConnectionType::Wired, 2048, None), 2048);
+        assert_eq!(chunk_size(ConnectionType::Wired, 0, None), 4096);
+        assert_eq!(chunk_size(ConnectionType::Wired, 2048, Some(511)), 508);
+        // Requests past the brain's window are capped.
+        assert_eq!(chunk_size(ConnectionType::Wired, 2048, Some(4096)), 2048);
+        assert_eq!(chunk_size(ConnectionType::Wired, 2048, Some(0)), 4);
+    }
+
+    #[test]
+    fn combined_progress_weighting() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut overall: Option<ProgressCallback> = Some(Box::new({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(progress)
+        }));
+
+        // The second of two files, each 100 bytes long
+        // The second file was compressed down to 10 bytes
+        let mut callback = combined_progress(None, &mut overall, 100, 100, 200).unwrap();
+        callback(0, 10);
+        callback(5, 10);
+        callback(10, 10);
+        drop(callback);
+
+        assert_eq!(*reported.lock().unwrap(), [50.0, 75.0, 100.0]);
+    }
+
+    const LOAD_ADDR: u32 = 0x0380_0000;
+
+    fn upload(data: Vec<u8>) -> UploadFile<'static> {
+        UploadFile {
+            filename: FixedLengthString::new("data.bin".to_string()).unwrap(),
+            filetype: FixedLengthString::new("bin".to_string()).unwrap(),
+            vendor: None,
+            data,
+            target: None,
+            load_addr: LOAD_ADDR,
+            linked_file: None,
+            after_upload: FileExitAction::DoNothing,
+            offset: 0,
+            compress: false,
+            chunk_size: Some(4),
+            progress_callback: None,
+            cancel: None,
+        }
+    }
+
+    /// Scripts the brain opening a transfer, as the init packet's timestamp can't be matched.
+    fn expect_transfer_start(mock: &mut MockConnection) {
+        let mut reply = 4096u16.to_le_bytes().to_vec();
+        reply.extend(3_145_728u32.to_le_bytes());
+        reply.extend(0u32.to_le_bytes());
+        mock.expect_any_request()
+            .queue_reply(cdc2_reply(17, 0x76, &reply));
+    }
+
+    fn expect_write(mock: &mut MockConnection, data: &[u8], offset: u32, ack: u8) {
+        mock.expect_request(
+            WriteFilePacket::new(WriteFilePayload {
+                address: (LOAD_ADDR + offset) as _,
+                chunk_data: data[offset as usize..][..4].to_vec(),
+            })
+            .encode()
+            .unwrap(),
+        )
+        .queue_reply(cdc2_reply(19, ack, &[]));
+    }
+
+    fn expect_transfer_end(mock: &mut MockConnection, ack: u8) {
+        mock.expect_request(
+            ExitFileTransferPacket::new(FileExitAction::DoNothing)
+                .encode()
+                .unwrap(),
+        )
+        .queue_reply(cdc2_reply(18, ack, &[]));
+    }
+
+    #[tokio::test]
+    async fn resume_after_disconnect() {
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        expect_transfer_start(&mut mock);
+        expect_write(&mut mock, &data, 0, 0x76);
+        // The link drops while the second chunk is being written.
+        mock.fail_sends(5);
+        expect_write(&mut mock, &data, 4, 0x76);
+        expect_transfer_end(&mut mock, 0x76);
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut upload = UploadFile {
+            progress_callback: Some(Box::new({
+                let reported = reported.clone();
+                move |sent, total| reported.lock().unwrap().push((sent, total))
+            })),
+            ..upload(data)
+        };
+
+        let result = upload.execute(&mut mock).await;
+        assert!(matches!(result, Err(MockError::SendFailed)));
+        assert_eq!(upload.offset, 4);
+
+        // The brain still has the transfer open, so only the rest of the file is written.
+        upload.execute(&mut mock).await.unwrap();
+        assert_eq!(upload.offset, 8);
+        assert!(mock.is_finished());
+        drop(upload);
+        assert_eq!(*reported.lock().unwrap(), [(4, 8), (8, 8)]);
+    }
+
+    #[tokio::test]
+    async fn restart_when_transfer_closed() {
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        // The brain was restarted, so it no longer has the transfer open.
+        expect_write(&mut mock, &data, 4, 0xD4);
+        expect_transfer_start(&mut mock);
+        expect_write(&mut mock, &data, 0, 0x76);
+        expect_write(&mut mock, &data, 4, 0x76);
+        expect_transfer_end(&mut mock, 0x76);
+
+        let mut upload = upload(data).resume(4);
+        upload.execute(&mut mock).await.unwrap();
+
+        assert_eq!(upload.offset, 8);
+        assert!(mock.is_finished());
+    }
+
+    #[tokio::test]
+    async fn brain_crc_mismatch() {
+        let data = vec![1, 2, 3, 4];
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        expect_transfer_start(&mut mock);
+        expect_write(&mut mock, &data, 0, 0x76);
+        // The brain's CRC of what it received doesn't match the one it was sent.
+        expect_transfer_end(&mut mock, 0xD2);
+
+        let result = upload(data.clone()).execute(&mut mock).await;
+        assert!(matches!(
+            result,
+            Err(MockError::Command(CommandError::CrcMismatch { expected })) if expected == crc32(&data)
+        ));
+        assert!(mock.is_finished());
+    }
+}
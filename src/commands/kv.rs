@@ -0,0 +1,70 @@
+use crate::{
+    connection::Connection,
+    packets::kv::{
+        ReadKeyValuePacket, ReadKeyValueReplyPacket, WriteKeyValuePacket, WriteKeyValuePayload,
+        WriteKeyValueReplyPacket,
+    },
+    string::{FixedLengthString, VarLengthString},
+};
+
+use super::Command;
+
+/// Reads a value from the brain's key-value store.
+///
+/// Keys are at most 31 bytes long, or this fails with
+/// [`EncodeError::StringTooLong`](crate::encode::EncodeError::StringTooLong).
+#[derive(Debug, Clone)]
+pub struct ReadKeyValue {
+    pub key: String,
+}
+impl Command for ReadKeyValue {
+    type Output = String;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let value = connection
+            .packet_handshake::<ReadKeyValueReplyPacket>(
+                connection.timeout(),
+                5,
+                ReadKeyValuePacket::new(FixedLengthString::new(self.key.clone())?),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(value.0)
+    }
+}
+
+/// Writes a value to the brain's key-value store.
+///
+/// Keys are at most 31 bytes long and values at most 255 bytes long, or this fails with
+/// [`EncodeError::StringTooLong`](crate::encode::EncodeError::StringTooLong).
+#[derive(Debug, Clone)]
+pub struct WriteKeyValue {
+    pub key: String,
+    pub value: String,
+}
+impl Command for WriteKeyValue {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        connection
+            .packet_handshake::<WriteKeyValueReplyPacket>(
+                connection.timeout(),
+                5,
+                WriteKeyValuePacket::new(WriteKeyValuePayload {
+                    key: VarLengthString::new(self.key.clone())?,
+                    value: VarLengthString::new(self.value.clone())?,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    connection::Connection,
+    packets::log::{
+        GetLogCountPacket, GetLogCountReplyPacket, Log, ReadLogPagePacket, ReadLogPagePayload,
+        ReadLogPageReplyPacket,
+    },
+};
+
+use super::Command;
+
+/// How many log entries to ask for at once.
+const LOG_PAGE_SIZE: u32 = 50;
+
+/// Reads every entry in the brain's event log, from oldest to newest.
+///
+/// Entries are returned as the raw [`Log`]s the brain sent. What their codes mean hasn't been
+/// worked out yet, so they aren't decoded into descriptions. Entries also don't record a
+/// J2000 timestamp, only [how long after boot](Log::time_since_boot) they were logged.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadEventLog;
+impl Command for ReadEventLog {
+    type Output = Vec<Log>;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let count = connection
+            .packet_handshake::<GetLogCountReplyPacket>(
+                connection.timeout(),
+                5,
+                GetLogCountPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?
+            .count;
+
+        // The count comes from the brain, so only reserve room for one page up front.
+        let mut entries = Vec::with_capacity(count.min(LOG_PAGE_SIZE) as usize);
+        // Offsets count back from the newest entry, so the oldest page has the largest offset.
+        let mut remaining = count;
+        while remaining > 0 {
+            let page_size = remaining.min(LOG_PAGE_SIZE);
+            let page = connection
+                .packet_handshake::<ReadLogPageReplyPacket>(
+                    connection.timeout(),
+                    5,
+                    ReadLogPagePacket::new(ReadLogPagePayload {
+                        offset: remaining,
+                        count: page_size,
+                    }),
+                )
+                .await?
+                .try_into_inner()?;
+
+            entries.extend(page.entries.into_inner());
+            remaining -= page_size;
+        }
+
+        Ok(entries)
+    }
+}
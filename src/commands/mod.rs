@@ -1,10 +1,23 @@
 use std::future::Future;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
-use crate::connection::Connection;
+use thiserror::Error;
 
+use crate::{connection::Connection, packets::cdc2::Cdc2Ack};
+
+pub mod device;
+pub mod factory;
 pub mod file;
+pub mod kv;
+pub mod log;
+pub mod program;
+pub mod radio;
 #[cfg(feature = "screen-command")]
 pub mod screen;
+pub mod system;
 
 pub trait Command {
     type Output;
@@ -14,3 +27,56 @@ pub trait Command {
         connection: &mut C,
     ) -> impl Future<Output = Result<Self::Output, C::Error>>;
 }
+
+/// A command failed for a reason other than the connection or the packets themselves.
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+    #[error("Slot {0} is out of range, expected 1-8")]
+    InvalidSlot(u8),
+    #[error("Slot {0} has no program in it")]
+    EmptySlot(u8),
+    #[error("The file the brain received doesn't match the CRC32 it was sent ({expected:#010x})")]
+    CrcMismatch { expected: u32 },
+    #[error("Program ini could not be parsed: {0}")]
+    InvalidIni(#[from] serde_ini::de::Error),
+    #[error("The brain refused to enable factory mode: {0}")]
+    FactoryModeRefused(Cdc2Ack),
+    #[error("Touch at ({x}, {y}) is off the 480x272 screen")]
+    TouchOutOfBounds { x: u16, y: u16 },
+    #[error("The command was cancelled")]
+    Cancelled,
+    #[error(
+        "Expected to read the chunk at {expected:#x}, but the brain sent the one at {found:#x}"
+    )]
+    ChunkMismatch { expected: u32, found: u32 },
+    #[error("The brain's battery is too low ({0}%), charge it and try again")]
+    LowBattery(u8),
+    #[error("The shared connection stopped before the command could finish")]
+    ConnectionStopped,
+}
+
+/// Stops a long-running command, such as a file transfer, from another task or thread.
+///
+/// Clones share the same state, so one clone can be given to the command and another kept
+/// to cancel it with. Commands check the token between chunks of data, and fail with
+/// [`CommandError::Cancelled`] once it has been cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every command holding a clone of this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true once [`CancellationToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
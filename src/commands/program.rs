@@ -0,0 +1,374 @@
+use log::info;
+
+use crate::{
+    connection::Connection,
+    encode::EncodeError,
+    packets::{
+        file::{
+            FileExitAction, FileLoadAction, FileVendor, LoadFileActionPacket,
+            LoadFileActionPayload, LoadFileActionReplyPacket,
+        },
+        program::{
+            GetSlot1To4InfoPacket, GetSlot1To4InfoReplyPacket, GetSlot5To8InfoPacket,
+            GetSlot5To8InfoReplyPacket, SlotIcon,
+        },
+        system::{GetSystemFlagsPacket, GetSystemFlagsReplyPacket},
+    },
+    string::FixedLengthString,
+};
+
+use super::{
+    file::{file_metadata, DownloadFile, ProgramIniConfig, UploadFile},
+    Command, CommandError,
+};
+
+/// The longest program name, in bytes, that fits on the brain's program menu.
+pub const MAX_SLOT_NAME_LEN: usize = 32;
+
+/// Runs the program in one of the brain's 8 program slots.
+///
+/// `slot` is 1-indexed, as shown on the brain's screen.
+#[derive(Debug, Clone, Copy)]
+pub struct RunProgram {
+    pub slot: u8,
+}
+impl Command for RunProgram {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        if !(1..=8).contains(&self.slot) {
+            return Err(CommandError::InvalidSlot(self.slot).into());
+        }
+
+        // Program files are named after the 0-indexed slot, like `UploadProgram` does.
+        let file_name = FixedLengthString::new(format!("slot{}.bin", self.slot - 1))?;
+        if file_metadata(connection, FileVendor::User, &file_name)
+            .await?
+            .is_none()
+        {
+            return Err(CommandError::EmptySlot(self.slot).into());
+        }
+
+        connection
+            .packet_handshake::<LoadFileActionReplyPacket>(
+                connection.timeout(),
+                5,
+                LoadFileActionPacket::new(LoadFileActionPayload {
+                    vendor: FileVendor::User,
+                    action: FileLoadAction::Run,
+                    file_name,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        info!("Running program in slot {}", self.slot);
+        Ok(())
+    }
+}
+
+/// Stops the program running on the brain.
+///
+/// Does nothing if no program is running.
+#[derive(Debug, Clone, Copy)]
+pub struct StopProgram;
+impl Command for StopProgram {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemFlagsPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+        if flags.current_program == 0 {
+            info!("No program is running");
+            return Ok(());
+        }
+
+        // Stopping doesn't need to name the program that is running.
+        connection
+            .packet_handshake::<LoadFileActionReplyPacket>(
+                connection.timeout(),
+                5,
+                LoadFileActionPacket::new(LoadFileActionPayload {
+                    vendor: FileVendor::User,
+                    action: FileLoadAction::Stop,
+                    file_name: FixedLengthString::new(String::new())?,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        info!("Stopped the running program");
+        Ok(())
+    }
+}
+
+/// A program that is running on the brain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunningProgram {
+    /// The 1-indexed slot the program was run from.
+    ///
+    /// Programs built into the brain have their own numbers outside of 1 to 8,
+    /// such as 129 for the Clawbot program and 145 for the Drive program.
+    pub slot: u8,
+    pub name: String,
+}
+
+/// Reads which program is running on the brain.
+///
+/// Returns `None` if the brain is sitting at its menus.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRunningProgram;
+impl Command for GetRunningProgram {
+    type Output = Option<RunningProgram>;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemFlagsPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+        let slot = flags.current_program;
+
+        let name = match slot {
+            0 => return Ok(None),
+            129 => "Clawbot".to_string(),
+            145 => "Drive".to_string(),
+            1..=8 => {
+                let payload = if slot <= 4 {
+                    connection
+                        .packet_handshake::<GetSlot1To4InfoReplyPacket>(
+                            connection.timeout(),
+                            5,
+                            GetSlot1To4InfoPacket::new(()),
+                        )
+                        .await?
+                        .try_into_inner()?
+                } else {
+                    connection
+                        .packet_handshake::<GetSlot5To8InfoReplyPacket>(
+                            connection.timeout(),
+                            5,
+                            GetSlot5To8InfoPacket::new(()),
+                        )
+                        .await?
+                        .try_into_inner()?
+                };
+
+                let index = (slot as usize - 1) % 4;
+                payload
+                    .slots
+                    .into_inner()
+                    .into_iter()
+                    .nth(index)
+                    .map(|slot| slot.name.into_inner().trim_end_matches('\0').to_string())
+                    .unwrap_or_default()
+            }
+            _ => String::new(),
+        };
+
+        Ok(Some(RunningProgram { slot, name }))
+    }
+}
+
+/// Renames a program slot and changes its icon without reuploading the program.
+///
+/// Only the `name` and `icon` lines of the slot's ini file are rewritten, so any other keys in
+/// it are kept as they were. `slot` is 1-indexed, as shown on the brain's screen.
+#[derive(Debug, Clone)]
+pub struct SetSlotInfo {
+    pub slot: u8,
+    pub name: String,
+    pub icon: SlotIcon,
+}
+impl Command for SetSlotInfo {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        if !(1..=8).contains(&self.slot) {
+            return Err(CommandError::InvalidSlot(self.slot).into());
+        }
+        if self.name.len() > MAX_SLOT_NAME_LEN {
+            return Err(EncodeError::StringTooLong {
+                len: self.name.len(),
+                max: MAX_SLOT_NAME_LEN,
+            }
+            .into());
+        }
+
+        let file_name = FixedLengthString::new(format!("slot{}.ini", self.slot - 1))?;
+        let Some(metadata) = file_metadata(connection, FileVendor::User, &file_name).await? else {
+            return Err(CommandError::EmptySlot(self.slot).into());
+        };
+
+        let ini = connection
+            .execute_command(DownloadFile {
+                filename: file_name.clone(),
+                filetype: FixedLengthString::new("ini".to_string())?,
+                size: metadata.size,
+                vendor: FileVendor::User,
+                target: None,
+                load_addr: metadata.load_address,
+                chunk_size: None,
+                progress_callback: None,
+                cancel: None,
+            })
+            .await?;
+        // Make sure this is a program's ini before editing it.
+        serde_ini::from_bytes::<ProgramIniConfig>(&ini).map_err(CommandError::InvalidIni)?;
+        let ini = set_program_keys(
+            &String::from_utf8_lossy(&ini),
+            &self.name,
+            &self.icon.file_name(),
+        );
+
+        connection
+            .execute_command(UploadFile {
+                filename: file_name,
+                filetype: FixedLengthString::new("ini".to_string())?,
+                vendor: None,
+                data: ini.into_bytes(),
+                target: None,
+                load_addr: metadata.load_address,
+                linked_file: None,
+                after_upload: FileExitAction::DoNothing,
+                offset: 0,
+                compress: false,
+                chunk_size: None,
+                progress_callback: None,
+                cancel: None,
+            })
+            .await?;
+
+        info!("Renamed slot {} to {}", self.slot, self.name);
+        Ok(())
+    }
+}
+
+/// Replaces the values of `name` and `icon` in an ini's `[program]` section, leaving every
+/// other line untouched.
+fn set_program_keys(ini: &str, name: &str, icon: &str) -> String {
+    let mut section = "";
+    ini.split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            if let Some(header) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = header.trim();
+            } else if let (Some((key, _)), "program") = (line.split_once('='), section) {
+                let value = match key.trim() {
+                    "name" => Some(name),
+                    "icon" => Some(icon),
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+                    return format!("{}={value}{ending}", key.trim_end());
+                }
+            }
+
+            line.to_string()
+        })
+        .collect()
+}
+
+/// A program in one of the brain's program slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlotInfo {
+    pub name: String,
+    pub icon: SlotIcon,
+    /// The size of the program's binary in bytes.
+    pub size: u32,
+}
+
+/// Reads the programs in all 8 slots, as shown on the brain's program menu.
+///
+/// Empty slots are `None`.
+#[derive(Debug, Clone, Copy)]
+pub struct GetAllSlots;
+impl Command for GetAllSlots {
+    type Output = [Option<SlotInfo>; 8];
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let first = connection
+            .packet_handshake::<GetSlot1To4InfoReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSlot1To4InfoPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+        let second = connection
+            .packet_handshake::<GetSlot5To8InfoReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSlot5To8InfoPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        let mut slots: [Option<SlotInfo>; 8] = Default::default();
+        for (half, payload) in [first, second].into_iter().enumerate() {
+            let used: [bool; 4] = std::array::from_fn(|index| payload.is_used(index));
+            for (index, slot) in payload.slots.into_inner().into_iter().enumerate().take(4) {
+                if !used[index] {
+                    continue;
+                }
+                let slot_index = half * 4 + index;
+
+                // The slot info doesn't include the program's size, so ask for its binary.
+                let file_name = FixedLengthString::new(format!("slot{}.bin", slot_index))?;
+                let size = file_metadata(connection, FileVendor::User, &file_name)
+                    .await?
+                    .map_or(0, |metadata| metadata.size);
+
+                slots[slot_index] = Some(SlotInfo {
+                    name: slot.name.into_inner().trim_end_matches('\0').to_string(),
+                    icon: slot.icon,
+                    size,
+                });
+            }
+        }
+
+        Ok(slots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::set_program_keys;
+
+    #[test]
+    fn program_keys() {
+        let ini = "[project]\nname=project\nide=PROS\n\n[program]\r\nname=old\r\nslot=0\r\nicon = USER902x.bmp\r\ncustom=kept\r\n";
+
+        assert_eq!(
+            set_program_keys(ini, "new", "USER001x.bmp"),
+            "[project]\nname=project\nide=PROS\n\n[program]\r\nname=new\r\nslot=0\r\nicon=USER001x.bmp\r\ncustom=kept\r\n"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use log::{info, warn};
+
+use crate::{
+    connection::Connection,
+    packets::radio::{
+        GetRadioStatusPacket, GetRadioStatusReplyPacket, RadioChannel, SelectRadioChannelPacket,
+        SelectRadioChannelPayload, SelectRadioChannelReplyPacket,
+    },
+};
+
+use super::Command;
+
+/// Switches the radio between the pit and download channels.
+///
+/// The controller and brain have to re-establish their link on the new channel, so a wireless
+/// connection will drop for a few seconds afterwards. Bluetooth mode has no channel and can
+/// only be selected from the brain's settings screen.
+#[derive(Debug, Clone, Copy)]
+pub struct SetRadioMode {
+    pub channel: RadioChannel,
+}
+impl Command for SetRadioMode {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        if connection.connection_type().is_controller() {
+            warn!("Switching radio channels will briefly drop the wireless link");
+        }
+
+        connection
+            .packet_handshake::<SelectRadioChannelReplyPacket>(
+                connection.timeout(),
+                5,
+                SelectRadioChannelPacket::new(SelectRadioChannelPayload {
+                    channel: self.channel,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        info!("Switched radio to the {:?} channel", self.channel);
+        Ok(())
+    }
+}
+
+/// The health of the brain's wireless link to a controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RadioLink {
+    /// Whether a controller is linked to the brain.
+    pub connected: bool,
+    /// Link quality from 0 to 100.
+    pub quality_percent: u8,
+    /// Signal strength in dBm.
+    pub strength: i16,
+    pub channel: i8,
+}
+
+/// Reads the signal strength and quality of the radio link.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRadioStatus;
+impl Command for GetRadioStatus {
+    type Output = RadioLink;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetRadioStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetRadioStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(RadioLink {
+            connected: status.device != 0,
+            quality_percent: status.quality.min(100) as u8,
+            strength: status.strength,
+            channel: status.channel,
+        })
+    }
+}
@@ -1,9 +1,8 @@
-use std::time::Duration;
-
 use log::info;
 
 use crate::{
     connection::Connection,
+    decode::DecodeError,
     packets::{
         capture::{ScreenCapturePacket, ScreenCaptureReplyPacket},
         dash::{
@@ -15,12 +14,59 @@ use crate::{
     string::FixedLengthString,
 };
 
-use super::{file::DownloadFile, Command};
+use super::{file::DownloadFile, Command, CommandError};
+
+/// The width of the brain's screen in pixels.
+pub const SCREEN_WIDTH: u16 = 480;
+/// The height of the brain's screen in pixels.
+pub const SCREEN_HEIGHT: u16 = 272;
+
+/// The width of the brain's framebuffer in pixels, including the padding past the screen's edge.
+pub const FRAMEBUFFER_STRIDE: u16 = 512;
+
+/// The raw contents of the brain's framebuffer.
+///
+/// The framebuffer is [`FRAMEBUFFER_STRIDE`] pixels wide and [`SCREEN_HEIGHT`] pixels tall,
+/// stored row by row with one little-endian `0x00RRGGBB` word per pixel. Only the first
+/// [`SCREEN_WIDTH`] pixels of each row are visible on the screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer(Vec<u8>);
+impl Framebuffer {
+    /// The raw framebuffer bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the raw framebuffer bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
 
+    /// Crops off the padding and converts each pixel to RGB8.
+    ///
+    /// The result is [`SCREEN_WIDTH`] by [`SCREEN_HEIGHT`] pixels, stored row by row.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.0
+            .chunks_exact(FRAMEBUFFER_STRIDE as usize * 4)
+            .flat_map(|row| row[..SCREEN_WIDTH as usize * 4].chunks_exact(4))
+            // little endian
+            .flat_map(|p| [p[2], p[1], p[0]])
+            .collect()
+    }
+
+    /// Converts the visible part of the framebuffer to an image.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_vec(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, self.to_rgb8())
+            .expect("Framebuffer should be a whole capture")
+    }
+}
+
+/// Captures the brain's 480x272 screen as a [`Framebuffer`].
 #[derive(Debug, Clone, Copy)]
 pub struct ScreenCapture;
 impl Command for ScreenCapture {
-    type Output = image::RgbImage;
+    type Output = Framebuffer;
 
     async fn execute<C: Connection + ?Sized>(
         &mut self,
@@ -29,47 +75,49 @@ impl Command for ScreenCapture {
         // Tell the brain we want to take a screenshot
         connection
             .packet_handshake::<ScreenCaptureReplyPacket>(
-                Duration::from_millis(100),
+                connection.timeout(),
                 5,
                 ScreenCapturePacket::new(()),
             )
             .await?;
 
+        let size = FRAMEBUFFER_STRIDE as u32 * SCREEN_HEIGHT as u32 * 4;
+
         // Grab the image data
-        let cap = connection
+        let mut cap = connection
             .execute_command(DownloadFile {
                 filename: FixedLengthString::new("screen".to_string()).unwrap(),
                 filetype: FixedLengthString::new("".to_string()).unwrap(),
                 vendor: FileVendor::Sys,
                 target: Some(FileDownloadTarget::Cbuf),
                 load_addr: 0,
-                size: 512 * 272 * 4,
-                progress_callback: Some(Box::new(|progress| {
-                    info!("Downloading screen: {:.2}%", progress)
+                size,
+                chunk_size: None,
+                progress_callback: Some(Box::new(|received, total| {
+                    info!("Downloading screen: {}/{} bytes", received, total)
                 })),
+                cancel: None,
             })
-            .await
-            .unwrap();
-
-        let colors = cap
-            .chunks(4)
-            .filter_map(|p| {
-                if p.len() == 4 {
-                    // little endian
-                    let color = [p[2], p[1], p[0]];
-                    Some(color)
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .collect::<Vec<_>>();
+            .await?;
+
+        // The brain sent back less than a whole framebuffer
+        if cap.len() < size as usize {
+            return Err(DecodeError::UnexpectedEof {
+                needed: size as usize,
+                available: cap.len(),
+            }
+            .into());
+        }
 
-        let image = image::RgbImage::from_vec(512, 272, colors).unwrap();
-        Ok(image::GenericImageView::view(&image, 0, 0, 480, 272).to_image())
+        cap.truncate(size as usize);
+
+        Ok(Framebuffer(cap))
     }
 }
 
+/// Presses or releases a point on the brain's screen, as if it were touched.
+///
+/// The origin is the top left corner of the screen.
 #[derive(Debug)]
 pub struct MockTouch {
     pub x: u16,
@@ -83,9 +131,17 @@ impl Command for MockTouch {
         &mut self,
         connection: &mut C,
     ) -> Result<Self::Output, C::Error> {
+        if self.x >= SCREEN_WIDTH || self.y >= SCREEN_HEIGHT {
+            return Err(CommandError::TouchOutOfBounds {
+                x: self.x,
+                y: self.y,
+            }
+            .into());
+        }
+
         connection
             .packet_handshake::<SendDashTouchReplyPacket>(
-                Duration::from_millis(100),
+                connection.timeout(),
                 5,
                 SendDashTouchPacket::new(SendDashTouchPayload {
                     x: self.x,
@@ -98,6 +154,7 @@ impl Command for MockTouch {
     }
 }
 
+/// Taps a point on the brain's screen by pressing and then releasing it.
 #[derive(Debug)]
 pub struct MockTap {
     pub x: u16,
@@ -129,6 +186,9 @@ impl Command for MockTap {
     }
 }
 
+/// Switches the brain's display to one of its dashboard screens.
+///
+/// Raw screen IDs can be checked with [`DashScreen::try_from`].
 #[derive(Debug)]
 pub struct OpenDashScreen {
     pub dash: DashScreen,
@@ -141,7 +201,7 @@ impl Command for OpenDashScreen {
     ) -> Result<Self::Output, C::Error> {
         connection
             .packet_handshake::<SelectDashReplyPacket>(
-                Duration::from_millis(100),
+                connection.timeout(),
                 5,
                 SelectDashPacket::new(SelectDashPayload {
                     screen: self.dash,
@@ -153,3 +213,24 @@ impl Command for OpenDashScreen {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Framebuffer, FRAMEBUFFER_STRIDE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn framebuffer_to_rgb8() {
+        let mut data = vec![0; FRAMEBUFFER_STRIDE as usize * SCREEN_HEIGHT as usize * 4];
+        data[..4].copy_from_slice(&0x00112233u32.to_le_bytes());
+        // Padding past the visible width must be dropped.
+        data[SCREEN_WIDTH as usize * 4] = 0xFF;
+
+        let rgb = Framebuffer(data).to_rgb8();
+        assert_eq!(
+            rgb.len(),
+            SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 3
+        );
+        assert_eq!(rgb[..3], [0x11, 0x22, 0x33]);
+        assert!(rgb[3..].iter().all(|&byte| byte == 0));
+    }
+}
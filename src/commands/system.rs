@@ -0,0 +1,285 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    connection::{Connection, ConnectionError},
+    packets::controller::{
+        CompetitionControlPacket, CompetitionControlPayload, CompetitionControlReplyPacket,
+    },
+    packets::system::{
+        CompetitionMode, GetSystemFlagsPacket, GetSystemFlagsReplyPacket, GetSystemStatusPacket,
+        GetSystemStatusReplyPacket, GetSystemVersionPacket, GetSystemVersionReplyPacket,
+        ProductType, SystemFlagBits,
+    },
+    version::Version,
+};
+
+use super::{Command, CommandError};
+
+/// The firmware versions running on a V5 device.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SystemVersion {
+    /// Whether the host is talking to a brain or to a controller.
+    pub product_type: ProductType,
+    /// The VEXos version.
+    pub system_version: Version,
+    pub cpu0_version: Version,
+    pub cpu1_version: Version,
+    pub touch_version: Version,
+}
+
+/// Reads the VEXos and processor firmware versions.
+///
+/// When connected through a controller, the processor versions are those of the brain
+/// the controller is paired with.
+#[derive(Debug, Clone, Copy)]
+pub struct GetSystemVersion;
+impl Command for GetSystemVersion {
+    type Output = SystemVersion;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let version = connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemVersionPacket::new(()),
+            )
+            .await?
+            .payload;
+
+        let status = connection
+            .packet_handshake::<GetSystemStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(SystemVersion {
+            product_type: version.product_type,
+            system_version: status.system_version,
+            cpu0_version: status.cpu0_version,
+            cpu1_version: status.cpu1_version,
+            touch_version: status.touch_version,
+        })
+    }
+}
+
+/// The ID that is unique to each brain, which can be used to tell brains apart.
+///
+/// Formats as 8 uppercase hexadecimal digits, such as `0123ABCD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId(pub u32);
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.0)
+    }
+}
+
+/// Reads the unique ID of the brain.
+///
+/// Returns `None` if the device doesn't report one.
+#[derive(Debug, Clone, Copy)]
+pub struct GetDeviceId;
+impl Command for GetDeviceId {
+    type Output = Option<DeviceId>;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetSystemStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(status.details.map(|details| DeviceId(details.unique_id)))
+    }
+}
+
+/// The battery levels of the brain and its controllers, each from 0 to 100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatteryStatus {
+    pub brain_percent: u8,
+    pub controller_percent: u8,
+    pub partner_controller_percent: u8,
+}
+
+/// Reads the battery levels of the brain and its controllers.
+///
+/// The brain only reports battery levels in steps of 8%, and doesn't report voltages.
+#[derive(Debug, Clone, Copy)]
+pub struct GetBatteryStatus;
+impl Command for GetBatteryStatus {
+    type Output = BatteryStatus;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemFlagsPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(BatteryStatus {
+            brain_percent: flags.battery_percent(),
+            controller_percent: flags.controller_battery_percent(),
+            partner_controller_percent: flags.partner_controller_battery_percent(),
+        })
+    }
+}
+
+/// The state of the brain and its radio link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BrainStatus {
+    pub battery_charging: bool,
+    pub radio_available: bool,
+    pub radio_connected: bool,
+    /// Whether the radio is in data mode rather than match mode.
+    pub radio_data_mode: bool,
+    pub vexnet_mode: bool,
+    pub controller_tethered: bool,
+    pub partner_controller_connected: bool,
+    pub field_controller_connected: bool,
+    /// The part of a match the brain is in, if the brain reported it.
+    pub competition_mode: Option<CompetitionMode>,
+}
+
+/// Reads whether the brain is charging, how its controllers are connected and
+/// which part of a match it is in.
+#[derive(Debug, Clone, Copy)]
+pub struct GetSystemStatus;
+impl Command for GetSystemStatus {
+    type Output = BrainStatus;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemFlagsPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?
+            .flags;
+
+        let details = connection
+            .packet_handshake::<GetSystemStatusReplyPacket>(
+                connection.timeout(),
+                5,
+                GetSystemStatusPacket::new(()),
+            )
+            .await?
+            .try_into_inner()?
+            .details;
+
+        Ok(BrainStatus {
+            battery_charging: flags.contains(SystemFlagBits::BATTERY_CHARGING),
+            radio_available: flags.contains(SystemFlagBits::RADIO_AVAILABLE),
+            radio_connected: flags.contains(SystemFlagBits::RADIO_CONNECTED),
+            radio_data_mode: flags.contains(SystemFlagBits::RADIO_DATA_MODE),
+            vexnet_mode: flags.contains(SystemFlagBits::VEXNET_MODE),
+            controller_tethered: flags.contains(SystemFlagBits::CONTROLLER_TETHERED),
+            partner_controller_connected: flags.contains(SystemFlagBits::PARTNER_CONTROLLER),
+            field_controller_connected: details
+                .as_ref()
+                .is_some_and(|details| details.field_controller_connected()),
+            competition_mode: details.as_ref().map(|details| details.competition_mode()),
+        })
+    }
+}
+
+/// Puts the brain into a competition mode for bench testing, without a competition switch.
+///
+/// This must be sent through a controller, which then acts as a field controller for the
+/// brain it is paired with. It is only meant for testing programs and has no effect on
+/// real matches, which are run by the field control system.
+#[derive(Debug, Clone, Copy)]
+pub struct SimulateCompetitionMode {
+    pub mode: CompetitionMode,
+}
+impl Command for SimulateCompetitionMode {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        connection
+            .packet_handshake::<CompetitionControlReplyPacket>(
+                connection.timeout(),
+                5,
+                CompetitionControlPacket::new(CompetitionControlPayload {
+                    mode: self.mode,
+                    match_time: 0,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+
+        Ok(())
+    }
+}
+
+/// Runs a command, failing with [`CommandError::LowBattery`] if the brain's battery is too low.
+///
+/// The battery is checked before the command starts. If the command then times out or
+/// loses the connection, it is checked again, since a brain browning out mid-transfer
+/// otherwise looks just like a bad cable. Each check costs an extra packet.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckBattery<T> {
+    pub command: T,
+    /// The lowest battery level, from 0 to 100, to run the command at.
+    pub min_percent: u8,
+}
+impl<T> CheckBattery<T> {
+    /// Returns the error to fail with if `percent` is too low.
+    fn low_battery(&self, percent: u8) -> Option<CommandError> {
+        (percent < self.min_percent).then_some(CommandError::LowBattery(percent))
+    }
+}
+impl<T: Command> Command for CheckBattery<T> {
+    type Output = T::Output;
+
+    async fn execute<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection.execute_command(GetBatteryStatus).await?;
+        if let Some(error) = self.low_battery(status.brain_percent) {
+            return Err(error.into());
+        }
+
+        match self.command.execute(connection).await {
+            Err(error) if error.is_retryable() || error.is_disconnect() => {
+                // If the brain can't be reached at all, the original error is more useful.
+                match connection.execute_command(GetBatteryStatus).await {
+                    Ok(status) => Err(self
+                        .low_battery(status.brain_percent)
+                        .map_or(error, Into::into)),
+                    Err(_) => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+}
@@ -1,22 +1,28 @@
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 use btleplug::api::{
-    Central, CentralEvent, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+    BDAddr, Central, CentralEvent, Characteristic, Manager as _, Peripheral as _,
+    PeripheralProperties, ScanFilter, WriteType,
 };
 use btleplug::platform::{Manager, Peripheral};
 use log::{debug, info, trace, warn};
 use thiserror::Error;
 use tokio::select;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
-use crate::connection::trim_packets;
+use crate::commands::CommandError;
+use crate::connection::take_packet;
 use crate::decode::{Decode, DecodeError};
 use crate::encode::{Encode, EncodeError};
 use crate::packets::cdc2::Cdc2Ack;
 
-use super::{Connection, ConnectionType, RawPacket};
+use super::{
+    Connection, ConnectionError, ConnectionType, PacketDirection, PacketObserver, RawPacket,
+    DEFAULT_TIMEOUT,
+};
 
 /// The BLE GATT Service that V5 Brains provide
 pub const V5_SERVICE: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb13d5);
@@ -34,6 +40,10 @@ pub const CHARACTERISTIC_PAIRING: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_87
 
 pub const UNPAIRED_MAGIC: u32 = 0xdeadface;
 
+/// The standard BLE battery level GATT characteristic
+pub const CHARACTERISTIC_BATTERY_LEVEL: Uuid =
+    Uuid::from_u128(0x00002a19_0000_1000_8000_00805f9b34fb); // READ | NOTIFY
+
 #[derive(Debug, Clone)]
 pub struct BluetoothDevice(pub Peripheral);
 
@@ -41,13 +51,31 @@ impl BluetoothDevice {
     pub async fn connect(&self) -> Result<BluetoothConnection, BluetoothError> {
         BluetoothConnection::open(self.clone()).await
     }
+
+    /// The Bluetooth address of the brain.
+    pub fn address(&self) -> BDAddr {
+        self.0.address()
+    }
+
+    /// The name the brain advertises, which is its robot name.
+    pub async fn name(&self) -> Result<Option<String>, BluetoothError> {
+        Ok(self
+            .0
+            .properties()
+            .await?
+            .and_then(|properties| properties.local_name))
+    }
 }
 
-/// Discover and locate bluetooth-compatible V5 peripherals.
-pub async fn find_devices(
+/// Scans for peripherals that advertise the [`V5_SERVICE`] for up to `scan_time`.
+///
+/// `on_brain` is called every time a brain is discovered or its advertisement changes, so the
+/// same brain may be seen more than once. The scan stops early once it returns true. To
+/// simply collect the brains that were found, use [`find_devices`].
+pub async fn scan(
     scan_time: Duration,
-    max_device_count: Option<usize>,
-) -> Result<Vec<BluetoothDevice>, BluetoothError> {
+    mut on_brain: impl FnMut(BluetoothDevice, PeripheralProperties) -> bool,
+) -> Result<(), BluetoothError> {
     // Create a new bluetooth device manager.
     let manager = Manager::new().await?;
 
@@ -64,11 +92,7 @@ pub async fn find_devices(
     // has found a device.
     let mut events = adapter.events().await?;
 
-    // List of devices that we'll add to during discovery.
-    let mut devices = Vec::<BluetoothDevice>::new();
-
     // Scan for peripherals using the V5 service UUID.
-    let scan_start_time = Instant::now();
     adapter
         .start_scan(ScanFilter {
             services: vec![V5_SERVICE],
@@ -76,36 +100,61 @@ pub async fn find_devices(
         .await?;
 
     // Listen for events. When the adapter indicates that a device has been discovered,
-    // we'll ensure that the peripheral is correct and add it to our device list.
-    while let Some(event) = events.next().await {
-        match event {
-            CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => {
-                let peripheral = adapter.peripheral(&id).await?;
-
-                if let Some(properties) = peripheral.properties().await? {
-                    if properties.services.contains(&V5_SERVICE) {
-                        // Assuming the peripheral contains the V5 service UUID, we have a brain.
-                        debug!("Found V5 brain at {}", peripheral.address());
-
-                        devices.push(BluetoothDevice(peripheral));
-
-                        // Break the discovery loop if we have found enough devices.
-                        if let Some(count) = max_device_count {
-                            if devices.len() == count {
-                                break;
-                            }
-                        }
-                    }
+    // we'll ensure that the peripheral is correct before handing it over.
+    let discovery = async {
+        while let Some(event) = events.next().await {
+            let (CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id)) = event
+            else {
+                continue;
+            };
+
+            let peripheral = adapter.peripheral(&id).await?;
+            if let Some(properties) = peripheral.properties().await? {
+                // Assuming the peripheral contains the V5 service UUID, we have a brain.
+                if properties.services.contains(&V5_SERVICE)
+                    && on_brain(BluetoothDevice(peripheral), properties)
+                {
+                    break;
                 }
             }
-            _ => {}
         }
 
-        // Also break if we've exceeded the provided scan time.
-        if scan_start_time.elapsed() > scan_time {
-            break;
+        Ok::<_, BluetoothError>(())
+    };
+
+    // Stop once the scan time is up, even if no more events arrive.
+    let result = timeout(scan_time, discovery).await.unwrap_or(Ok(()));
+    // Don't leave the adapter scanning if discovery failed.
+    adapter.stop_scan().await?;
+
+    result
+}
+
+/// Discover and locate bluetooth-compatible V5 peripherals.
+///
+/// Scans for up to `scan_time`, or until `max_device_count` brains have been found. Only
+/// peripherals that advertise the [`V5_SERVICE`] are returned.
+pub async fn find_devices(
+    scan_time: Duration,
+    max_device_count: Option<usize>,
+) -> Result<Vec<BluetoothDevice>, BluetoothError> {
+    // List of devices that we'll add to during discovery.
+    let mut devices = Vec::<BluetoothDevice>::new();
+
+    let scan_start_time = Instant::now();
+    scan(scan_time, |device, _| {
+        // Brains are reported again every time their advertisement changes.
+        if devices.iter().any(|found| found.0.id() == device.0.id()) {
+            return false;
         }
-    }
+
+        debug!("Found V5 brain at {}", device.address());
+        devices.push(device);
+
+        // Stop discovering if we have found enough devices.
+        max_device_count.is_some_and(|count| devices.len() >= count)
+    })
+    .await?;
 
     info!(
         "Found {} devices in {:?}",
@@ -116,6 +165,34 @@ pub async fn find_devices(
     Ok(devices)
 }
 
+/// Scans for a brain advertising the robot name `name` and connects to it.
+///
+/// Fails with [`BluetoothError::NotConnected`] if no brain by that name is found within
+/// `scan_time`. The returned connection may still need to be
+/// [paired](BluetoothConnection::request_pairing) before commands can be sent.
+pub async fn connect_by_name(
+    name: &str,
+    scan_time: Duration,
+) -> Result<BluetoothConnection, BluetoothError> {
+    let mut found = None;
+    // Names often only show up in a later update of a brain's advertisement.
+    scan(scan_time, |device, properties| {
+        if properties.local_name.as_deref() != Some(name) {
+            return false;
+        }
+
+        debug!("Found V5 brain {:?} at {}", name, device.address());
+        found = Some(device);
+        true
+    })
+    .await?;
+
+    found
+        .ok_or_else(|| BluetoothError::NotConnected(name.to_string()))?
+        .connect()
+        .await
+}
+
 pub struct BluetoothConnection {
     pub peripheral: Peripheral,
     pub system_tx: Characteristic,
@@ -125,6 +202,9 @@ pub struct BluetoothConnection {
     pub pairing: Characteristic,
 
     incoming_packets: Vec<RawPacket>,
+    user_output: VecDeque<u8>,
+    observer: PacketObserver,
+    timeout: Duration,
 }
 
 impl BluetoothConnection {
@@ -177,6 +257,9 @@ impl BluetoothConnection {
             pairing: pairing.ok_or(BluetoothError::MissingCharacteristic)?,
 
             incoming_packets: Vec::new(),
+            user_output: VecDeque::new(),
+            observer: PacketObserver::default(),
+            timeout: DEFAULT_TIMEOUT,
         };
 
         connection
@@ -188,12 +271,23 @@ impl BluetoothConnection {
         Ok(connection)
     }
 
+    /// Returns true if this connection has been authenticated with the brain's PIN.
+    ///
+    /// Packets can't be sent until it has, or they fail with [`BluetoothError::PairingRequired`].
     pub async fn is_paired(&self) -> Result<bool, BluetoothError> {
         let auth_bytes = self.peripheral.read(&self.pairing).await?;
+        let auth: [u8; 4] = auth_bytes
+            .get(0..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(BluetoothError::NoResponse)?;
 
-        Ok(u32::from_be_bytes(auth_bytes[0..4].try_into().unwrap()) != UNPAIRED_MAGIC)
+        Ok(u32::from_be_bytes(auth) != UNPAIRED_MAGIC)
     }
 
+    /// Asks the brain to show a 4-digit PIN on its screen.
+    ///
+    /// Follow this with [`authenticate_pairing`](Self::authenticate_pairing) once the user has
+    /// entered the PIN they see.
     pub async fn request_pairing(&mut self) -> Result<(), BluetoothError> {
         self.peripheral
             .write(
@@ -206,6 +300,23 @@ impl BluetoothConnection {
         Ok(())
     }
 
+    /// Completes pairing with the PIN shown on the brain's screen, one digit per byte.
+    ///
+    /// The brain echoes the PIN back if it was correct. Otherwise this fails with
+    /// [`BluetoothError::IncorrectPin`], and a new PIN has to be requested.
+    ///
+    /// ```no_run
+    /// # async fn example(
+    /// #     mut connection: vex_v5_serial::connection::bluetooth::BluetoothConnection,
+    /// # ) -> Result<(), vex_v5_serial::connection::bluetooth::BluetoothError> {
+    /// if !connection.is_paired().await? {
+    ///     connection.request_pairing().await?;
+    ///     // Read the PIN off of the brain's screen
+    ///     connection.authenticate_pairing([1, 2, 3, 4]).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn authenticate_pairing(&mut self, pin: [u8; 4]) -> Result<(), BluetoothError> {
         self.peripheral
             .write(&self.pairing, &pin, WriteType::WithoutResponse)
@@ -220,24 +331,72 @@ impl BluetoothConnection {
         Ok(())
     }
 
-    async fn receive_one_packet(&mut self) -> Result<(), BluetoothError> {
+    /// Finds a GATT characteristic the brain provides, such as one from a standard BLE service.
+    ///
+    /// Fails with [`BluetoothError::MissingCharacteristic`] if the brain doesn't have it.
+    pub fn characteristic(&self, uuid: Uuid) -> Result<Characteristic, BluetoothError> {
+        self.peripheral
+            .characteristics()
+            .into_iter()
+            .find(|characteristic| characteristic.uuid == uuid)
+            .ok_or(BluetoothError::MissingCharacteristic)
+    }
+
+    /// Reads the current value of a GATT characteristic, without going through the V5 service.
+    pub async fn read_characteristic(&self, uuid: Uuid) -> Result<Vec<u8>, BluetoothError> {
+        let characteristic = self.characteristic(uuid)?;
+
+        Ok(self.peripheral.read(&characteristic).await?)
+    }
+
+    /// Reads the brain's battery level, from 0 to 100, from the standard BLE battery service.
+    pub async fn battery_level(&self) -> Result<u8, BluetoothError> {
+        self.read_characteristic(CHARACTERISTIC_BATTERY_LEVEL)
+            .await?
+            .first()
+            .copied()
+            .ok_or(BluetoothError::NoResponse)
+    }
+
+    /// Sets a callback that is given the raw bytes of every packet sent and received.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: impl Fn(PacketDirection, &[u8]) + Send + Sync + 'static,
+    ) {
+        self.observer.set(observer);
+    }
+
+    /// Waits for the next notification and files it away by which port it came from.
+    ///
+    /// Returns the UUID of the characteristic that sent it.
+    async fn receive_notification(&mut self) -> Result<Uuid, BluetoothError> {
         //TODO: get notifications and store it rather than creating it every time this method is called
         let mut notifs = self.peripheral.notifications().await?;
+        let notification = notifs.next().await.ok_or(BluetoothError::NoResponse)?;
 
-        loop {
-            let Some(notification) = notifs.next().await else {
-                return Err(BluetoothError::NoResponse);
-            };
-
-            if notification.uuid == CHARACTERISTIC_SYSTEM_TX {
+        let uuid = notification.uuid;
+        match uuid {
+            CHARACTERISTIC_SYSTEM_TX => {
                 let data = notification.value;
                 debug!("Received packet: {:x?}", data);
+                self.observer.observe(PacketDirection::Received, &data);
                 let packet = RawPacket::new(data);
                 self.incoming_packets.push(packet);
-                break;
             }
+            CHARACTERISTIC_USER_TX => {
+                trace!("Received user output: {:x?}", notification.value);
+                self.user_output.extend(notification.value);
+            }
+            _ => {}
         }
 
+        Ok(uuid)
+    }
+
+    async fn receive_one_packet(&mut self) -> Result<(), BluetoothError> {
+        // User output can arrive while we wait, so keep it for read_user.
+        while self.receive_notification().await? != CHARACTERISTIC_SYSTEM_TX {}
+
         Ok(())
     }
 }
@@ -249,6 +408,14 @@ impl Connection for BluetoothConnection {
         ConnectionType::Bluetooth
     }
 
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), BluetoothError> {
         if !self.is_paired().await? {
             return Err(BluetoothError::PairingRequired);
@@ -258,6 +425,7 @@ impl Connection for BluetoothConnection {
         let encoded = packet.encode()?;
 
         trace!("Sending packet: {:x?}", encoded);
+        self.observer.observe(PacketDirection::Sent, &encoded);
 
         // Write the packet to the system rx characteristic.
         self.peripheral
@@ -267,18 +435,33 @@ impl Connection for BluetoothConnection {
         Ok(())
     }
 
+    async fn send_packets(&mut self, packets: &[impl Encode]) -> Result<(), BluetoothError> {
+        // A single characteristic write can't be longer than the MTU, so
+        // pack as many whole packets into each write as will fit.
+        let mut batch = Vec::new();
+        for packet in packets {
+            let encoded = packet.encode()?;
+            if !batch.is_empty() && batch.len() + encoded.len() > Self::MAX_PACKET_SIZE {
+                self.send_packet(std::mem::take(&mut batch)).await?;
+            }
+            batch.extend(encoded);
+        }
+
+        if !batch.is_empty() {
+            self.send_packet(batch).await?;
+        }
+
+        Ok(())
+    }
+
     async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, BluetoothError> {
         // Return an error if the right packet is not received within the timeout
         select! {
             result = async {
                 loop {
-                    for packet in self.incoming_packets.iter_mut() {
-                        if let Ok(decoded) = packet.decode_and_use::<P>() {
-                            trim_packets(&mut self.incoming_packets);
-                            return Ok(decoded);
-                        }
+                    if let Some(decoded) = take_packet::<P>(&mut self.incoming_packets) {
+                        return Ok(decoded?);
                     }
-                    trim_packets(&mut self.incoming_packets);
                     self.receive_one_packet().await?;
                 }
             } => result,
@@ -286,12 +469,42 @@ impl Connection for BluetoothConnection {
         }
     }
 
-    async fn read_user(&mut self, _buf: &mut [u8]) -> Result<usize, BluetoothError> {
-        todo!();
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, BluetoothError> {
+        // Wait for the program to print something
+        while self.user_output.is_empty() {
+            self.receive_notification().await?;
+        }
+
+        let len = buf.len().min(self.user_output.len());
+        for (byte, output) in buf.iter_mut().zip(self.user_output.drain(..len)) {
+            *byte = output;
+        }
+
+        Ok(len)
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, BluetoothError> {
+        if !self.is_paired().await? {
+            return Err(BluetoothError::PairingRequired);
+        }
+
+        // Like packets, a single write can't be longer than the MTU.
+        for chunk in buf.chunks(Self::MAX_PACKET_SIZE) {
+            trace!("Writing user input: {:x?}", chunk);
+            self.peripheral
+                .write(&self.user_rx, chunk, WriteType::WithoutResponse)
+                .await?;
+        }
+
+        Ok(buf.len())
     }
 
-    async fn write_user(&mut self, _buf: &[u8]) -> Result<usize, BluetoothError> {
-        todo!();
+    async fn close(self) -> Result<(), BluetoothError> {
+        self.peripheral.unsubscribe(&self.system_tx).await?;
+        self.peripheral.unsubscribe(&self.user_tx).await?;
+        self.peripheral.disconnect().await?;
+
+        Ok(())
     }
 }
 
@@ -305,8 +518,10 @@ pub enum BluetoothError {
     DecodeError(#[from] DecodeError),
     #[error("Packet timeout")]
     Timeout,
-    #[error("NACK received: {0:?}")]
+    #[error("NACK received: {0}")]
     Nack(#[from] Cdc2Ack),
+    #[error("Command failed: {0}")]
+    Command(#[from] CommandError),
     #[error("Bluetooth Error")]
     Btleplug(#[from] btleplug::Error),
     #[error("No response received over bluetooth")]
@@ -319,4 +534,27 @@ pub enum BluetoothError {
     IncorrectPin,
     #[error("Pairing is required")]
     PairingRequired,
+    #[error("No brain named {0:?} was found")]
+    NotConnected(String),
+}
+impl ConnectionError for BluetoothError {
+    fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            Self::IoError(_) | Self::NoResponse | Self::Btleplug(btleplug::Error::NotConnected)
+        )
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::DecodeError(e) => e.is_corrupted(),
+            _ => matches!(
+                self,
+                Self::IoError(_)
+                    | Self::Timeout
+                    | Self::NoResponse
+                    | Self::Btleplug(btleplug::Error::TimedOut(_))
+            ),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    connection::{bluetooth, serial, Connection, ConnectionType},
+    commands::CommandError,
+    connection::{bluetooth, serial, Connection, ConnectionError, ConnectionType, PacketDirection},
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
     packets::cdc2::Cdc2Ack,
@@ -10,6 +11,8 @@ use thiserror::Error;
 
 use super::{bluetooth::BluetoothError, serial::SerialError};
 
+// Connections are long-lived and rarely moved, so boxing the larger variant isn't worth it.
+#[allow(clippy::large_enum_variant)]
 pub enum GenericConnection {
     Bluetooth(bluetooth::BluetoothConnection),
     Serial(serial::SerialConnection),
@@ -24,6 +27,27 @@ impl Connection for GenericConnection {
         }
     }
 
+    fn timeout(&self) -> Duration {
+        match self {
+            GenericConnection::Bluetooth(c) => c.timeout(),
+            GenericConnection::Serial(s) => s.timeout(),
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        match self {
+            GenericConnection::Bluetooth(c) => c.set_timeout(timeout),
+            GenericConnection::Serial(s) => s.set_timeout(timeout),
+        }
+    }
+
+    fn partial_packet(&self) -> &[u8] {
+        match self {
+            GenericConnection::Bluetooth(c) => c.partial_packet(),
+            GenericConnection::Serial(s) => s.partial_packet(),
+        }
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), GenericError> {
         match self {
             GenericConnection::Bluetooth(c) => c.send_packet(packet).await?,
@@ -32,6 +56,14 @@ impl Connection for GenericConnection {
         Ok(())
     }
 
+    async fn send_packets(&mut self, packets: &[impl Encode]) -> Result<(), GenericError> {
+        match self {
+            GenericConnection::Bluetooth(c) => c.send_packets(packets).await?,
+            GenericConnection::Serial(s) => s.send_packets(packets).await?,
+        };
+        Ok(())
+    }
+
     async fn receive_packet<P: Decode>(
         &mut self,
         timeout: std::time::Duration,
@@ -55,6 +87,14 @@ impl Connection for GenericConnection {
             GenericConnection::Serial(s) => s.write_user(buf).await?,
         })
     }
+
+    async fn close(self) -> Result<(), GenericError> {
+        match self {
+            GenericConnection::Bluetooth(c) => c.close().await?,
+            GenericConnection::Serial(s) => s.close().await?,
+        };
+        Ok(())
+    }
 }
 impl GenericConnection {
     /// Returns whether the connection is over bluetooth.
@@ -70,6 +110,17 @@ impl GenericConnection {
         self.connection_type().is_controller()
     }
 
+    /// Sets a callback that is given the raw bytes of every packet sent and received.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: impl Fn(PacketDirection, &[u8]) + Send + Sync + 'static,
+    ) {
+        match self {
+            GenericConnection::Bluetooth(c) => c.set_packet_observer(observer),
+            GenericConnection::Serial(s) => s.set_packet_observer(observer),
+        }
+    }
+
     /// Checks if the connection is paired.
     /// If the connection is not over bluetooth, this function will return an error.
     pub async fn is_paired(&self) -> Result<bool, GenericError> {
@@ -140,7 +191,7 @@ pub async fn find_devices() -> Result<Vec<GenericDevice>, GenericError> {
         bluetooth_devices().map_err(GenericError::BluetoothError),
         serial_devices().map_err(GenericError::SerialError),
     }
-    .map(|(bluetooth, serial)| bluetooth.into_iter().chain(serial.into_iter()).collect())?;
+    .map(|(bluetooth, serial)| bluetooth.into_iter().chain(serial).collect())?;
     Ok(res)
 }
 
@@ -167,8 +218,28 @@ pub enum GenericError {
     EncodeError(#[from] EncodeError),
     #[error("Packet decoding error: {0}")]
     DecodeError(#[from] DecodeError),
-    #[error("NACK received: {0:?}")]
+    #[error("NACK received: {0}")]
     Nack(#[from] Cdc2Ack),
+    #[error("Command failed: {0}")]
+    Command(#[from] CommandError),
     #[error("Pairing is not supported over any connection other than Bluetooth")]
     PairingNotSupported,
 }
+impl ConnectionError for GenericError {
+    fn is_disconnect(&self) -> bool {
+        match self {
+            Self::SerialError(e) => e.is_disconnect(),
+            Self::BluetoothError(e) => e.is_disconnect(),
+            _ => false,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::SerialError(e) => e.is_retryable(),
+            Self::BluetoothError(e) => e.is_retryable(),
+            Self::DecodeError(e) => e.is_corrupted(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Wraps a [`Connection`] to measure how long the brain takes to reply to packets.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{Connection, ConnectionType};
+use crate::decode::Decode;
+use crate::encode::Encode;
+
+/// How many of the most recent round trips are averaged.
+const WINDOW_SIZE: usize = 16;
+
+/// A [`Connection`] that records the round-trip time of every reply it receives.
+///
+/// A round trip is timed from the last packet sent to the next packet successfully received,
+/// so a [`packet_handshake`](Connection::packet_handshake) that had to retry is only timed
+/// from its final attempt, and only the first reply to a batch sent with
+/// [`send_packets`](Connection::send_packets) is timed. Timing costs nothing on connections
+/// that aren't wrapped.
+///
+/// ```
+/// # use vex_v5_serial::connection::{latency::TimedConnection, mock::MockConnection, ConnectionType};
+/// let connection = TimedConnection::new(MockConnection::new(ConnectionType::Bluetooth));
+/// assert_eq!(connection.last_round_trip(), None);
+/// ```
+#[derive(Debug)]
+pub struct TimedConnection<C> {
+    connection: C,
+    sent_at: Option<Instant>,
+    round_trips: VecDeque<Duration>,
+}
+
+impl<C: Connection> TimedConnection<C> {
+    /// Starts timing the replies received on `connection`.
+    pub fn new(connection: C) -> Self {
+        Self {
+            connection,
+            sent_at: None,
+            round_trips: VecDeque::with_capacity(WINDOW_SIZE),
+        }
+    }
+
+    /// The round-trip time of the most recent reply.
+    pub fn last_round_trip(&self) -> Option<Duration> {
+        self.round_trips.back().copied()
+    }
+
+    /// The average round-trip time of the last 16 replies.
+    pub fn average_round_trip(&self) -> Option<Duration> {
+        if self.round_trips.is_empty() {
+            return None;
+        }
+
+        let total: Duration = self.round_trips.iter().sum();
+        Some(total / self.round_trips.len() as u32)
+    }
+
+    /// Forgets every round trip timed so far.
+    pub fn reset(&mut self) {
+        self.sent_at = None;
+        self.round_trips.clear();
+    }
+
+    /// Returns a reference to the underlying connection.
+    pub fn get_ref(&self) -> &C {
+        &self.connection
+    }
+
+    /// Returns a mutable reference to the underlying connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.connection
+    }
+
+    /// Returns the underlying connection.
+    pub fn into_inner(self) -> C {
+        self.connection
+    }
+}
+
+impl<C: Connection> Connection for TimedConnection<C> {
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection.connection_type()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.connection.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.connection.set_timeout(timeout);
+    }
+
+    fn partial_packet(&self) -> &[u8] {
+        self.connection.partial_packet()
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), C::Error> {
+        self.connection.send_packet(packet).await?;
+        self.sent_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    async fn send_packets(&mut self, packets: &[impl Encode]) -> Result<(), C::Error> {
+        self.connection.send_packets(packets).await?;
+        self.sent_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, C::Error> {
+        let packet = self.connection.receive_packet(timeout).await?;
+
+        if let Some(sent_at) = self.sent_at.take() {
+            if self.round_trips.len() == WINDOW_SIZE {
+                self.round_trips.pop_front();
+            }
+            self.round_trips.push_back(sent_at.elapsed());
+        }
+
+        Ok(packet)
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, C::Error> {
+        self.connection.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, C::Error> {
+        self.connection.write_user(buf).await
+    }
+
+    async fn close(self) -> Result<(), C::Error> {
+        self.connection.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TimedConnection;
+    use crate::connection::{mock::MockConnection, Connection, ConnectionType};
+    use crate::fixtures::{system_version_reply, SYSTEM_VERSION_REQUEST};
+    use crate::packets::system::{GetSystemVersionPacket, GetSystemVersionReplyPacket};
+
+    #[tokio::test]
+    async fn times_handshakes() {
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        mock.queue_reply(system_version_reply(1))
+            .queue_reply(system_version_reply(1));
+        mock.expect_request(SYSTEM_VERSION_REQUEST.to_vec());
+        let mut connection = TimedConnection::new(mock);
+
+        connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(
+                Duration::from_millis(100),
+                1,
+                GetSystemVersionPacket::new(()),
+            )
+            .await
+            .unwrap();
+        assert!(connection.last_round_trip().is_some());
+        assert_eq!(
+            connection.average_round_trip(),
+            connection.last_round_trip()
+        );
+
+        // Replies that weren't asked for aren't round trips.
+        connection.reset();
+        connection
+            .receive_packet::<GetSystemVersionReplyPacket>(Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(connection.last_round_trip(), None);
+        assert!(connection.get_ref().is_finished());
+    }
+
+    #[tokio::test]
+    async fn times_batches() {
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        mock.expect_request(SYSTEM_VERSION_REQUEST.repeat(2))
+            .queue_reply(system_version_reply(1))
+            .queue_reply(system_version_reply(2));
+        let mut connection = TimedConnection::new(mock);
+
+        connection
+            .send_packets(&[
+                GetSystemVersionPacket::new(()),
+                GetSystemVersionPacket::new(()),
+            ])
+            .await
+            .unwrap();
+        connection
+            .receive_packets::<GetSystemVersionReplyPacket>(2, Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        // Only the first reply is timed from when the batch was sent.
+        assert!(connection.last_round_trip().is_some());
+        assert_eq!(connection.round_trips.len(), 1);
+        assert!(connection.get_ref().is_finished());
+    }
+}
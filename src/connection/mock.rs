@@ -0,0 +1,414 @@
+//! An in-memory [`Connection`] for testing commands without a physical brain.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use log::trace;
+use thiserror::Error;
+
+use crate::commands::CommandError;
+use crate::decode::{Decode, DecodeError};
+use crate::encode::{Encode, EncodeError};
+use crate::packets::cdc2::Cdc2Ack;
+
+use super::{Connection, ConnectionError, ConnectionType, DEFAULT_TIMEOUT};
+
+/// A scripted connection that checks outgoing packets and plays back canned replies.
+///
+/// Every packet sent must exactly match the next expected request, or `send_packet` panics.
+/// Requests and scripted send failures happen in the order they were added. Every call to `receive_packet` decodes the next canned reply, regardless of whether a
+/// request was sent in between.
+///
+/// ```
+/// # use vex_v5_serial::connection::mock::MockConnection;
+/// # use vex_v5_serial::connection::ConnectionType;
+/// let mut connection = MockConnection::new(ConnectionType::Wired);
+/// connection
+///     .expect_request(vec![0xC9, 0x36, 0xB8, 0x47, 0xA4])
+///     .queue_reply(vec![0xAA, 0x55, 0xA4, 0x00]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockConnection {
+    connection_type: ConnectionType,
+    expected_sends: VecDeque<ScriptedSend>,
+    replies: VecDeque<Vec<u8>>,
+    user_output: VecDeque<u8>,
+    user_input: Vec<u8>,
+    timeout: Duration,
+}
+
+/// What happens the next time a packet is sent.
+#[derive(Debug, Clone)]
+enum ScriptedSend {
+    /// The packet must be exactly these bytes.
+    Request(Vec<u8>),
+    /// Any packet is accepted.
+    AnyRequest,
+    Fail,
+    Disconnect,
+}
+
+impl MockConnection {
+    /// Creates a mock connection with nothing scripted.
+    pub fn new(connection_type: ConnectionType) -> Self {
+        Self {
+            connection_type,
+            expected_sends: VecDeque::new(),
+            replies: VecDeque::new(),
+            user_output: VecDeque::new(),
+            user_input: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Adds the encoded bytes of a packet that must be sent next.
+    pub fn expect_request(&mut self, request: Vec<u8>) -> &mut Self {
+        self.expected_sends
+            .push_back(ScriptedSend::Request(request));
+        self
+    }
+
+    /// Adds a packet that must be sent next without checking its bytes.
+    ///
+    /// This is for packets that can't be known ahead of time, such as ones carrying the
+    /// current time.
+    pub fn expect_any_request(&mut self) -> &mut Self {
+        self.expected_sends.push_back(ScriptedSend::AnyRequest);
+        self
+    }
+
+    /// Adds the raw bytes of a packet that the brain will reply with.
+    pub fn queue_reply(&mut self, reply: Vec<u8>) -> &mut Self {
+        self.replies.push_back(reply);
+        self
+    }
+
+    /// Makes the next `count` packets sent after the requests expected so far fail with
+    /// [`MockError::SendFailed`].
+    ///
+    /// Failed packets aren't checked against the expected requests.
+    pub fn fail_sends(&mut self, count: usize) -> &mut Self {
+        self.expected_sends
+            .extend(std::iter::repeat_n(ScriptedSend::Fail, count));
+        self
+    }
+
+    /// Makes the next `count` packets sent after the requests expected so far fail with
+    /// [`MockError::Disconnected`], as if the link to the brain had dropped.
+    ///
+    /// Failed packets aren't checked against the expected requests.
+    pub fn fail_sends_with_disconnect(&mut self, count: usize) -> &mut Self {
+        self.expected_sends
+            .extend(std::iter::repeat_n(ScriptedSend::Disconnect, count));
+        self
+    }
+
+    /// Adds bytes that a user program will print.
+    pub fn queue_user_output(&mut self, output: &[u8]) -> &mut Self {
+        self.user_output.extend(output);
+        self
+    }
+
+    /// Everything written to the user program so far.
+    pub fn user_input(&self) -> &[u8] {
+        &self.user_input
+    }
+
+    /// Returns true once every expected request has been sent, every scripted failure has
+    /// happened and every reply has been received.
+    pub fn is_finished(&self) -> bool {
+        self.expected_sends.is_empty() && self.replies.is_empty()
+    }
+}
+
+impl Connection for MockConnection {
+    type Error = MockError;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection_type
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), MockError> {
+        let encoded = packet.encode()?;
+        trace!("Sending packet: {:x?}", encoded);
+
+        match self.expected_sends.pop_front() {
+            Some(ScriptedSend::Request(expected)) => {
+                assert_eq!(encoded, expected, "Sent packet did not match the script")
+            }
+            Some(ScriptedSend::AnyRequest) => {}
+            Some(ScriptedSend::Fail) => return Err(MockError::SendFailed),
+            Some(ScriptedSend::Disconnect) => return Err(MockError::Disconnected),
+            None => panic!("Unexpected packet sent: {:x?}", encoded),
+        }
+
+        Ok(())
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, _timeout: Duration) -> Result<P, MockError> {
+        let reply = self.replies.pop_front().ok_or(MockError::Timeout)?;
+        trace!("Received packet: {:x?}", reply);
+
+        Ok(P::decode(reply)?)
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, MockError> {
+        let len = buf.len().min(self.user_output.len());
+        for (byte, output) in buf.iter_mut().zip(self.user_output.drain(..len)) {
+            *byte = output;
+        }
+
+        Ok(len)
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, MockError> {
+        self.user_input.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MockError {
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Packet timeout")]
+    Timeout,
+    #[error("Packet could not be sent")]
+    SendFailed,
+    #[error("Connection lost")]
+    Disconnected,
+    #[error("NACK received: {0}")]
+    Nack(#[from] Cdc2Ack),
+    #[error("Command failed: {0}")]
+    Command(#[from] CommandError),
+}
+impl ConnectionError for MockError {
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Disconnected)
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::DecodeError(e) => e.is_corrupted(),
+            _ => matches!(self, Self::Timeout | Self::SendFailed | Self::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::{MockConnection, MockError};
+    use crate::connection::{Connection, ConnectionType};
+    use crate::fixtures::{cdc2_reply, system_version_reply, SYSTEM_VERSION_REQUEST};
+    use crate::packets::system::{
+        GetSystemFlagsPacket, GetSystemFlagsReplyPacket, GetSystemVersionPacket,
+        GetSystemVersionReplyPacket,
+    };
+
+    #[tokio::test]
+    async fn handshake() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+            .queue_reply(system_version_reply(1));
+
+        let reply: GetSystemVersionReplyPacket = connection
+            .packet_handshake(
+                Duration::from_millis(100),
+                1,
+                GetSystemVersionPacket::new(()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(reply.payload.version.major, 1);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn batched() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection.expect_request(SYSTEM_VERSION_REQUEST.repeat(2));
+
+        connection
+            .send_packets(&[
+                GetSystemVersionPacket::new(()),
+                GetSystemVersionPacket::new(()),
+            ])
+            .await
+            .unwrap();
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn no_reply() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let result = connection
+            .receive_packet::<GetSystemVersionReplyPacket>(Duration::from_millis(100))
+            .await;
+
+        assert!(matches!(result, Err(MockError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn fails_fast() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        // A reply with an ack code this crate doesn't know will decode the same way every time.
+        connection
+            .expect_any_request()
+            .queue_reply(cdc2_reply(0x20, 0x42, &[]));
+
+        // A retry would send an unexpected packet and panic.
+        let result = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(
+                Duration::from_millis(100),
+                3,
+                GetSystemFlagsPacket::new(()),
+            )
+            .await;
+
+        assert!(matches!(result, Err(MockError::DecodeError(_))));
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn retries_truncated_reply() {
+        // The last byte of the first reply is lost on the way.
+        let mut truncated = system_version_reply(1);
+        truncated.pop();
+
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+            .queue_reply(truncated)
+            .expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+            .queue_reply(system_version_reply(1));
+
+        let reply = connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(
+                Duration::from_millis(100),
+                2,
+                GetSystemVersionPacket::new(()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(reply.payload.version.major, 1);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn retries_failed_send() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .fail_sends(1)
+            .expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+            .queue_reply(system_version_reply(1));
+
+        let reply = connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(
+                Duration::from_millis(100),
+                2,
+                GetSystemVersionPacket::new(()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(reply.payload.version.major, 1);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn raw() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .expect_request(vec![0xC9, 0x36, 0xB8, 0x47, 0xFF])
+            .queue_reply(vec![0xAA, 0x55, 0xFF, 0x01, 0x00]);
+
+        connection
+            .send_raw(&[0xC9, 0x36, 0xB8, 0x47, 0xFF])
+            .await
+            .unwrap();
+        let reply = connection
+            .receive_raw(Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        assert_eq!(reply, [0xAA, 0x55, 0xFF, 0x01, 0x00]);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .queue_reply(system_version_reply(1))
+            .queue_reply(system_version_reply(2));
+
+        let mut stream = pin!(connection.packet_stream::<GetSystemVersionReplyPacket>());
+        for major in [1, 2] {
+            let reply = stream.next().await.unwrap().unwrap();
+            assert_eq!(reply.payload.version.major, major);
+        }
+    }
+
+    #[cfg(any(feature = "serial", feature = "tcp"))]
+    #[tokio::test]
+    async fn fifo_rejects_invalid_utf8() {
+        let mut connection = MockConnection::new(ConnectionType::Controller);
+        let result = crate::connection::write_user_fifo(&mut connection, &[b'a', 0xFF]).await;
+
+        assert!(matches!(result, Err(MockError::EncodeError(_))));
+    }
+
+    #[tokio::test]
+    async fn output_lines() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection.queue_user_output(b"hello\nworld\r\npartial");
+
+        let mut lines = pin!(connection.user_output_lines());
+        for expected in ["hello", "world", "partial"] {
+            assert_eq!(lines.next().await.unwrap().unwrap(), expected);
+        }
+        assert!(lines.next().await.is_none());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "did not match")]
+    async fn unexpected_request() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection.expect_request(vec![0x00]);
+        let _ = connection
+            .send_packet(GetSystemVersionPacket::new(()))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn user_io() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection.queue_user_output(b"hello");
+
+        let mut buf = [0; 3];
+        assert_eq!(connection.read_user(&mut buf).await.unwrap(), 3);
+        assert_eq!(&buf, b"hel");
+
+        connection.write_user(b"hi").await.unwrap();
+        assert_eq!(connection.user_input(), b"hi");
+    }
+}
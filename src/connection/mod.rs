@@ -1,12 +1,17 @@
 //! Implements functions and structures for interacting with vex devices.
 
-use std::{future::Future, time::Instant};
+use std::future::Future;
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+use std::time::Instant;
 
-use log::{error, trace, warn};
+use futures::Stream;
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+use log::trace;
+use log::{error, warn};
 use std::time::Duration;
 
 use crate::{
-    commands::Command,
+    commands::{Command, CommandError},
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
     packets::cdc2::Cdc2Ack,
@@ -16,15 +21,23 @@ use crate::{
 pub mod bluetooth;
 #[cfg(all(feature = "serial", feature = "bluetooth"))]
 pub mod generic;
+pub mod latency;
+pub mod mock;
+pub mod reconnect;
 #[cfg(feature = "serial")]
 pub mod serial;
+pub mod shared;
+#[cfg(feature = "tcp")]
+pub mod tcp;
 
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
 #[derive(Debug, Clone)]
 pub(crate) struct RawPacket {
     bytes: Vec<u8>,
     used: bool,
     timestamp: Instant,
 }
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
 impl RawPacket {
     pub fn new(bytes: Vec<u8>) -> Self {
         Self {
@@ -39,17 +52,69 @@ impl RawPacket {
     }
 
     /// Decodes the packet into the given type.
-    /// If successful, marks the packet as used.
+    /// If successful, or if the packet is a malformed one of the given type, marks the packet
+    /// as used.
     /// # Note
     /// This function will **NOT** fail if the packet has already been used.
     pub fn decode_and_use<D: Decode>(&mut self) -> Result<D, DecodeError> {
-        let decoded = D::decode(self.bytes.clone())?;
-        self.used = true;
-        Ok(decoded)
+        let decoded = D::decode(self.bytes.clone());
+        if !decoded.as_ref().is_err_and(DecodeError::is_other_packet) {
+            self.used = true;
+        }
+        decoded
+    }
+}
+/// Which way a packet was travelling when it was observed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PacketDirection {
+    /// Sent to the device.
+    Sent,
+    /// Received from the device.
+    Received,
+}
+
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+type ObserverFn = dyn Fn(PacketDirection, &[u8]) + Send + Sync;
+
+/// An optional callback that sees the raw bytes of every packet on a connection.
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+#[derive(Default)]
+pub(crate) struct PacketObserver(Option<Box<ObserverFn>>);
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+impl PacketObserver {
+    pub fn set(&mut self, observer: impl Fn(PacketDirection, &[u8]) + Send + Sync + 'static) {
+        self.0 = Some(Box::new(observer));
+    }
+
+    pub fn observe(&self, direction: PacketDirection, bytes: &[u8]) {
+        if let Some(observer) = &self.0 {
+            observer(direction, bytes);
+        }
     }
 }
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+impl std::fmt::Debug for PacketObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PacketObserver")
+            .field(&self.0.as_ref().map(|_| "Fn"))
+            .finish()
+    }
+}
+
+/// The undecoded bytes of whatever packet is received next.
+struct RawBytes(Vec<u8>);
+impl Decode for RawBytes {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        Ok(Self(data.into_iter().collect()))
+    }
+}
+
+/// How long commands wait for each reply unless the connection is told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
 /// Removes old and used packets from the incoming packets buffer.
-pub(crate) fn trim_packets(packets: &mut Vec<RawPacket>) {
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+fn trim_packets(packets: &mut Vec<RawPacket>) {
     trace!("Trimming packets. Length before: {}", packets.len());
 
     // Remove packets that are obsolete
@@ -58,13 +123,248 @@ pub(crate) fn trim_packets(packets: &mut Vec<RawPacket>) {
     trace!("Trimmed packets. Length after: {}", packets.len());
 }
 
+/// Decodes the first buffered packet that is either a `P` or a malformed one, then trims the
+/// buffer.
+///
+/// Returns `None` if every buffered packet is a different kind, such as a late reply to an
+/// earlier command.
+#[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+pub(crate) fn take_packet<P: Decode>(
+    packets: &mut Vec<RawPacket>,
+) -> Option<Result<P, DecodeError>> {
+    let decoded = packets
+        .iter_mut()
+        .map(RawPacket::decode_and_use::<P>)
+        .find(|decoded| !decoded.as_ref().is_err_and(DecodeError::is_other_packet));
+    trim_packets(packets);
+
+    decoded
+}
+
+/// Reads a single host-bound packet from a stream of system port bytes.
+///
+/// Bytes are collected in `buf` until a whole packet has arrived, so reading can be
+/// cancelled, such as by a timeout, without losing what has arrived so far. Any bytes
+/// before the next header are discarded, so the stream resynchronizes after a dropped
+/// byte leaves it partway through a packet.
+#[cfg(any(feature = "serial", feature = "tcp"))]
+pub(crate) async fn read_packet<R, E>(reader: &mut R, buf: &mut Vec<u8>) -> Result<RawPacket, E>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    E: From<std::io::Error> + From<DecodeError>,
+{
+    use crate::{packets::HOST_BOUND_HEADER, varint::VarU16};
+    use log::debug;
+    use tokio::io::AsyncReadExt;
+
+    loop {
+        // Skip over garbage, keeping a trailing byte that could start the next header
+        let skipped = buf
+            .windows(2)
+            .position(|window| window == HOST_BOUND_HEADER)
+            .unwrap_or(buf.len() - usize::from(buf.last() == Some(&HOST_BOUND_HEADER[0])));
+        if skipped > 0 {
+            warn!("Skipped {} bytes before the next packet header", skipped);
+            buf.drain(..skipped);
+        }
+
+        // The header and ID are followed by a size that may be one or two bytes long
+        if let Some(&first_size_byte) = buf.get(3) {
+            let size = if VarU16::check_wide(first_size_byte) {
+                buf.get(3..5)
+                    .map(|size| VarU16::decode(size.to_vec()))
+                    .transpose()?
+                    .map(|size| 5 + size.into_inner() as usize)
+            } else {
+                Some(4 + VarU16::decode([first_size_byte])?.into_inner() as usize)
+            };
+
+            if let Some(len) = size.filter(|&len| buf.len() >= len) {
+                let packet = buf.drain(..len).collect::<Vec<_>>();
+                debug!("received packet: {:x?}", packet);
+
+                return Ok(RawPacket::new(packet));
+            }
+        }
+
+        let mut chunk = [0; 512];
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Reads user program output through the user FIFO packets.
+///
+/// This is used by connections that don't have a dedicated user port.
+#[cfg(any(feature = "serial", feature = "tcp"))]
+pub(crate) async fn read_user_fifo<C: Connection + ?Sized>(
+    connection: &mut C,
+    buf: &mut [u8],
+) -> Result<usize, C::Error> {
+    use crate::packets::controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket};
+
+    let mut data = Vec::new();
+    loop {
+        let fifo = connection
+            .packet_handshake::<UserFifoReplyPacket>(
+                connection.timeout(),
+                1,
+                UserFifoPacket::new(UserFifoPayload {
+                    channel: 1, // stdio channel
+                    write: None,
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+        if let Some(read) = fifo.data {
+            data.extend(read.0.as_bytes());
+            break;
+        }
+    }
+
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+
+    Ok(len)
+}
+
+/// The most bytes of stdin that fit in a single user FIFO packet.
+#[cfg(any(feature = "serial", feature = "tcp"))]
+const USER_FIFO_WRITE_SIZE: usize = 224;
+
+/// Writes to user program stdio through the user FIFO packets.
+///
+/// This is used by connections that don't have a dedicated user port. The packets carry
+/// strings, so `buf` must be valid UTF-8.
+#[cfg(any(feature = "serial", feature = "tcp"))]
+pub(crate) async fn write_user_fifo<C: Connection + ?Sized>(
+    connection: &mut C,
+    buf: &[u8],
+) -> Result<usize, C::Error> {
+    use crate::packets::controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket};
+    use crate::string::VarLengthString;
+
+    let mut text = std::str::from_utf8(buf).map_err(EncodeError::from)?;
+    while !text.is_empty() {
+        // Don't split a character across two packets
+        let mut len = text.len().min(USER_FIFO_WRITE_SIZE);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        let (chunk, rest) = text.split_at(len);
+
+        _ = connection
+            .packet_handshake::<UserFifoReplyPacket>(
+                connection.timeout(),
+                1,
+                UserFifoPacket::new(UserFifoPayload {
+                    channel: 1, // stdio channel
+                    write: Some(VarLengthString::new(chunk.to_string())?),
+                }),
+            )
+            .await?
+            .try_into_inner()?;
+        text = rest;
+    }
+
+    Ok(buf.len())
+}
+
+/// An exponentially growing delay between retries.
+///
+/// The first delay is `initial_delay`, and each one after that is `multiplier` times
+/// longer than the last, up to `max_delay`. A multiplier that doesn't give a valid delay,
+/// such as a negative or NaN one, or a delay too long to represent, jumps straight to
+/// `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial_delay: Duration,
+    pub multiplier: f64,
+    pub max_delay: Duration,
+}
+impl Backoff {
+    /// No delay at all between retries.
+    pub const fn none() -> Self {
+        Self {
+            initial_delay: Duration::ZERO,
+            multiplier: 1.0,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    /// A delay starting at `initial_delay` that grows by `multiplier` each retry, up to `max_delay`.
+    pub const fn exponential(
+        initial_delay: Duration,
+        multiplier: f64,
+        max_delay: Duration,
+    ) -> Self {
+        Self {
+            initial_delay,
+            multiplier,
+            max_delay,
+        }
+    }
+
+    /// Returns the endless sequence of delays to wait before each retry.
+    pub fn delays(&self) -> impl Iterator<Item = Duration> {
+        let backoff = *self;
+        std::iter::successors(
+            Some(backoff.initial_delay.min(backoff.max_delay)),
+            move |delay| {
+                let next = Duration::try_from_secs_f64(delay.as_secs_f64() * backoff.multiplier)
+                    .unwrap_or(backoff.max_delay);
+                Some(next.min(backoff.max_delay))
+            },
+        )
+    }
+}
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Classifies the errors returned by a [`Connection`].
+pub trait ConnectionError: std::error::Error {
+    /// Returns true if the error means the link to the device was lost.
+    ///
+    /// The connection will not recover on its own and must be reopened.
+    fn is_disconnect(&self) -> bool;
+
+    /// Returns true if sending the same packet again could succeed.
+    ///
+    /// Timeouts, I/O errors and replies corrupted on the way are usually transient, while a
+    /// packet that failed to encode or that the device rejected will fail the same way every
+    /// time.
+    fn is_retryable(&self) -> bool;
+}
+
 /// Represents an open connection to a V5 peripheral.
 #[allow(async_fn_in_trait)]
 pub trait Connection {
-    type Error: std::error::Error + From<EncodeError> + From<DecodeError> + From<Cdc2Ack>;
+    type Error: ConnectionError
+        + From<EncodeError>
+        + From<DecodeError>
+        + From<Cdc2Ack>
+        + From<CommandError>;
 
     fn connection_type(&self) -> ConnectionType;
 
+    /// How long commands wait for a reply to each packet.
+    ///
+    /// Commands that give the brain more work, such as finishing a file transfer, wait
+    /// for a multiple of this.
+    fn timeout(&self) -> Duration;
+
+    /// Sets how long commands wait for a reply to each packet.
+    ///
+    /// This defaults to [`DEFAULT_TIMEOUT`], which may be too short for a congested
+    /// Bluetooth link.
+    fn set_timeout(&mut self, timeout: Duration);
+
     /// Sends a packet.
     fn send_packet(&mut self, packet: impl Encode)
         -> impl Future<Output = Result<(), Self::Error>>;
@@ -75,12 +375,161 @@ pub trait Connection {
         timeout: Duration,
     ) -> impl Future<Output = Result<P, Self::Error>>;
 
+    /// The bytes received since the last whole packet, which don't make up a packet yet.
+    ///
+    /// After [`receive_packet`](Connection::receive_packet) times out, this tells a device
+    /// that sent nothing apart from one that sent a truncated or garbled reply. The bytes are
+    /// kept, so a packet that is still arriving can be completed by the next receive.
+    /// Connections that only ever receive whole packets always return nothing.
+    fn partial_packet(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Sends several packets in a single write.
+    ///
+    /// This saves a round trip per packet for streams of commands that don't need to wait
+    /// for each reply. The packets are written back to back, so the device will still
+    /// reply to each one individually.
+    async fn send_packets(&mut self, packets: &[impl Encode]) -> Result<(), Self::Error> {
+        let mut encoded = Vec::new();
+        for packet in packets {
+            packet.encode_into(&mut encoded)?;
+        }
+
+        self.send_packet(encoded).await
+    }
+
+    /// Receives `count` packets of the same type, such as the replies to [`Connection::send_packets`].
+    ///
+    /// The timeout applies to each packet separately.
+    async fn receive_packets<P: Decode>(
+        &mut self,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<P>, Self::Error> {
+        let mut packets = Vec::with_capacity(count);
+        for _ in 0..count {
+            packets.push(self.receive_packet(timeout).await?);
+        }
+
+        Ok(packets)
+    }
+
+    /// Sends bytes exactly as given, without encoding them as a packet.
+    ///
+    /// This is meant for experimenting with undocumented commands. The bytes must include
+    /// the device-bound header and any size or CRC the device expects.
+    async fn send_raw(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.send_packet(bytes.to_vec()).await
+    }
+
+    /// Receives the bytes of the next packet without decoding them.
+    ///
+    /// The device's replies are still split apart by their header and size, so this returns
+    /// one whole packet, header included, no matter which command it replies to.
+    async fn receive_raw(&mut self, timeout: Duration) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.receive_packet::<RawBytes>(timeout).await?.0)
+    }
+
+    /// Receives packets of the same type for as long as the connection stays open.
+    ///
+    /// This is meant for replies that keep arriving without being asked for again, such as
+    /// controller telemetry. Waiting for the next packet never times out, and packets of
+    /// other types are skipped over. The stream ends after yielding an error that
+    /// [is a disconnect](ConnectionError::is_disconnect).
+    ///
+    /// ```no_run
+    /// # async fn example(connection: &mut impl vex_v5_serial::connection::Connection) {
+    /// use futures::StreamExt;
+    /// use vex_v5_serial::packets::radio::GetRadioStatusReplyPacket;
+    ///
+    /// let mut stream = std::pin::pin!(connection.packet_stream::<GetRadioStatusReplyPacket>());
+    /// while let Some(packet) = stream.next().await {
+    ///     println!("{:?}", packet.map(|packet| packet.payload.quality));
+    /// }
+    /// # }
+    /// ```
+    fn packet_stream<P: Decode>(&mut self) -> impl Stream<Item = Result<P, Self::Error>> + '_ {
+        futures::stream::unfold(Some(self), |connection| async move {
+            let connection = connection?;
+            loop {
+                match connection.receive_packet(connection.timeout()).await {
+                    Ok(packet) => return Some((Ok(packet), Some(connection))),
+                    Err(e) if e.is_disconnect() => return Some((Err(e), None)),
+                    // Nothing arrived in time, so keep waiting.
+                    Err(e) if e.is_retryable() => continue,
+                    Err(e) => return Some((Err(e), Some(connection))),
+                }
+            }
+        })
+    }
+
     /// Read user program output.
     fn read_user(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
 
     /// Write to user program stdio.
     fn write_user(&mut self, buf: &[u8]) -> impl Future<Output = Result<usize, Self::Error>>;
 
+    /// Reads user program output one line at a time, like a terminal would show it.
+    ///
+    /// Output is buffered until a newline arrives, and the line is yielded without its
+    /// line ending. Invalid UTF-8 is replaced rather than treated as an error.
+    ///
+    /// The stream ends after yielding an error that [is a disconnect](ConnectionError::is_disconnect),
+    /// or once [`read_user`](Connection::read_user) reads nothing, after yielding any unfinished
+    /// last line. Only connections whose output can run out, such as a
+    /// [`MockConnection`](mock::MockConnection), ever read nothing. The others wait for more
+    /// output instead, so on them the stream goes on until the connection is lost.
+    ///
+    /// ```no_run
+    /// # async fn example(connection: &mut impl vex_v5_serial::connection::Connection) {
+    /// use futures::StreamExt;
+    ///
+    /// let mut lines = std::pin::pin!(connection.user_output_lines());
+    /// while let Some(Ok(line)) = lines.next().await {
+    ///     println!("{}", line);
+    /// }
+    /// # }
+    /// ```
+    fn user_output_lines(&mut self) -> impl Stream<Item = Result<String, Self::Error>> + '_ {
+        futures::stream::unfold(Some((self, Vec::new())), |state| async move {
+            let (connection, mut pending) = state?;
+            let mut buf = [0; 1024];
+            loop {
+                if let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+                    let line = pending.drain(..=end).collect::<Vec<_>>();
+                    let line = String::from_utf8_lossy(&line[..end]);
+                    let line = line.strip_suffix('\r').unwrap_or(&line).to_string();
+                    return Some((Ok(line), Some((connection, pending))));
+                }
+
+                match connection.read_user(&mut buf).await {
+                    Ok(0) if pending.is_empty() => return None,
+                    // Nothing more will arrive to finish the last line.
+                    Ok(0) => {
+                        let line = String::from_utf8_lossy(&pending).into_owned();
+                        return Some((Ok(line), None));
+                    }
+                    Ok(len) => pending.extend_from_slice(&buf[..len]),
+                    Err(e) if e.is_disconnect() => return Some((Err(e), None)),
+                    Err(e) if e.is_retryable() => continue,
+                    Err(e) => return Some((Err(e), Some((connection, pending)))),
+                }
+            }
+        })
+    }
+
+    /// Closes the connection, flushing any pending writes and releasing the device.
+    ///
+    /// Dropping a connection also closes it, but gives no chance to report errors
+    /// or to wait for the device to be released.
+    async fn close(self) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+
     /// Executes a [`Command`].
     async fn execute_command<C: Command>(
         &mut self,
@@ -96,19 +545,53 @@ pub trait Connection {
     ///
     /// # Note
     ///
-    /// This function will fail immediately if the given packet fails to encode.
+    /// This function will fail immediately if the given packet fails to encode,
+    /// or on any error that [isn't retryable](ConnectionError::is_retryable),
+    /// whether it happened while sending the packet or waiting for the reply.
     async fn packet_handshake<D: Decode>(
         &mut self,
         timeout: Duration,
         retries: usize,
         packet: impl Encode + Clone,
+    ) -> Result<D, Self::Error> {
+        self.packet_handshake_with_backoff(timeout, retries, Backoff::none(), packet)
+            .await
+    }
+
+    /// Sends a packet and waits for a response, sleeping between retries.
+    ///
+    /// This behaves like [`Connection::packet_handshake`], except that each retry
+    /// waits for the next delay in `backoff` before resending the packet.
+    async fn packet_handshake_with_backoff<D: Decode>(
+        &mut self,
+        timeout: Duration,
+        retries: usize,
+        backoff: Backoff,
+        packet: impl Encode + Clone,
     ) -> Result<D, Self::Error> {
         let mut last_error = None;
+        let mut delays = backoff.delays();
 
-        for _ in 0..retries {
-            self.send_packet(packet.clone()).await?;
-            match self.receive_packet::<D>(timeout).await {
+        // Always make at least one attempt, so there is an error to return.
+        for attempt in 0..retries.max(1) {
+            if attempt > 0 {
+                let delay = delays.next().unwrap_or_default();
+                // Avoid touching the timer at all for the default of no delay.
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+
+            let result = match self.send_packet(packet.clone()).await {
+                Ok(()) => self.receive_packet::<D>(timeout).await,
+                Err(e) => Err(e),
+            };
+            match result {
                 Ok(decoded) => return Ok(decoded),
+                Err(e) if !e.is_retryable() => {
+                    error!("Handshake failed with error: {:?}", e);
+                    return Err(e);
+                }
                 Err(e) => {
                     warn!("Handshake failed: {:?}. Retrying...", e);
                     last_error = Some(e);
@@ -140,3 +623,123 @@ impl ConnectionType {
         matches!(self, ConnectionType::Bluetooth)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Backoff;
+
+    #[cfg(feature = "serial")]
+    #[test]
+    fn resync_after_garbage() {
+        use std::future::Future;
+        use std::pin::pin;
+        use std::task::{Context, Poll, Waker};
+
+        use super::serial::SerialError;
+
+        // The tail of a packet that lost its first byte and ends in a stray 0xAA,
+        // followed by a whole packet.
+        let garbage = [0x55, 0xA4, 0x07, 0x01, 0xAA];
+        let packet = [0xAA, 0x55, 0xA4, 0x02, 0x01, 0x02];
+        let bytes = [garbage.as_slice(), packet.as_slice()].concat();
+        let mut reader = bytes.as_slice();
+        let mut buf = Vec::new();
+
+        let mut future = pin!(super::read_packet::<_, SerialError>(&mut reader, &mut buf));
+        let Poll::Ready(read) = future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        else {
+            panic!("Reading from a slice should never wait");
+        };
+
+        assert_eq!(read.unwrap().bytes, packet);
+    }
+
+    #[cfg(feature = "serial")]
+    #[test]
+    fn keeps_partial_packet() {
+        use std::future::Future;
+        use std::pin::pin;
+        use std::task::{Context, Poll, Waker};
+
+        use super::serial::SerialError;
+
+        // The reply stops partway through its payload.
+        let mut reader = [0xAA, 0x55, 0xA4, 0x07, 0x01, 0x02].as_slice();
+        let mut buf = Vec::new();
+
+        let read = {
+            let mut future = pin!(super::read_packet::<_, SerialError>(&mut reader, &mut buf));
+            let Poll::Ready(read) = future
+                .as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+            else {
+                panic!("Reading from a slice should never wait");
+            };
+            read
+        };
+
+        assert!(read.is_err());
+        assert_eq!(buf, [0xAA, 0x55, 0xA4, 0x07, 0x01, 0x02]);
+    }
+
+    #[cfg(any(feature = "serial", feature = "bluetooth", feature = "tcp"))]
+    #[test]
+    fn takes_corrupted_reply() {
+        use super::{take_packet, RawPacket};
+        use crate::decode::DecodeError;
+        use crate::fixtures::{cdc2_reply, read_file_reply};
+        use crate::packets::file::ReadFileReplyPacket;
+
+        let mut corrupted = read_file_reply(0x0380_0000, &[1, 2, 3, 4]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let mut packets = vec![
+            RawPacket::new(cdc2_reply(0x11, 0x76, &[0; 10])),
+            RawPacket::new(corrupted),
+            RawPacket::new(read_file_reply(0x0380_0000, &[1, 2, 3, 4])),
+        ];
+
+        let taken = take_packet::<ReadFileReplyPacket>(&mut packets);
+        assert!(matches!(taken, Some(Err(DecodeError::BadCrc { .. }))));
+        // The unrelated reply and the good one are left for whoever is waiting on them.
+        assert_eq!(packets.len(), 2);
+        assert!(take_packet::<ReadFileReplyPacket>(&mut packets).is_some_and(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn backoff_delays() {
+        let backoff =
+            Backoff::exponential(Duration::from_millis(100), 2.0, Duration::from_millis(500));
+        let delays = backoff.delays().take(5).collect::<Vec<_>>();
+
+        assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn invalid_backoff() {
+        for multiplier in [-1.0, f64::NAN, f64::INFINITY] {
+            let backoff =
+                Backoff::exponential(Duration::from_millis(100), multiplier, Duration::MAX);
+            let delays = backoff.delays().take(3).collect::<Vec<_>>();
+
+            assert_eq!(
+                delays,
+                [Duration::from_millis(100), Duration::MAX, Duration::MAX]
+            );
+        }
+
+        let backoff = Backoff::exponential(Duration::MAX, 2.0, Duration::MAX);
+        assert!(backoff.delays().take(3).all(|delay| delay == Duration::MAX));
+    }
+
+    #[test]
+    fn no_backoff() {
+        assert!(Backoff::none()
+            .delays()
+            .take(3)
+            .all(|delay| delay.is_zero()));
+    }
+}
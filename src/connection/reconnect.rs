@@ -0,0 +1,371 @@
+//! Wraps a [`Connection`] so that a dropped link is transparently reopened.
+
+use std::future::Future;
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::time::sleep;
+
+use super::{Backoff, Connection, ConnectionError, ConnectionType};
+use crate::decode::Decode;
+use crate::encode::Encode;
+
+/// How a [`ReconnectingConnection`] tries to reopen a lost connection.
+///
+/// After each failed attempt, the delay before the next one is multiplied by
+/// `multiplier`, up to `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    max_attempts: usize,
+    backoff: Backoff,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Backoff::exponential(Duration::from_millis(250), 2.0, Duration::from_secs(5)),
+        }
+    }
+}
+impl ReconnectPolicy {
+    /// Creates the default policy of 3 attempts starting 250ms apart.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of times to try reopening the connection before giving up.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the delay before the first attempt.
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.backoff.initial_delay = initial_delay;
+        self
+    }
+
+    /// Sets the factor the delay grows by after each failed attempt.
+    ///
+    /// A factor that doesn't give a valid delay, such as a negative one, makes every later
+    /// delay `max_delay`.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.backoff.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the longest delay between two attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.backoff.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the delays between attempts all at once.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// A [`Connection`] that reopens itself when the underlying link is lost.
+///
+/// Whenever the inner connection fails with an error that
+/// [is a disconnect](ConnectionError::is_disconnect), `connect` is called to open a
+/// new one according to the [`ReconnectPolicy`] and the operation is retried once.
+/// Replies that were in flight when the link dropped are lost, so a
+/// [`packet_handshake`](Connection::packet_handshake) will time out and resend its packet.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), vex_v5_serial::connection::bluetooth::BluetoothError> {
+/// use vex_v5_serial::connection::{bluetooth, reconnect::{ReconnectingConnection, ReconnectPolicy}};
+///
+/// let device = bluetooth::find_devices(std::time::Duration::from_secs(5), Some(1)).await?.remove(0);
+/// let connection = ReconnectingConnection::connect(
+///     || device.connect(),
+///     ReconnectPolicy::new().max_attempts(5),
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReconnectingConnection<C, F> {
+    connection: C,
+    connect: F,
+    policy: ReconnectPolicy,
+}
+
+impl<C, F, Fut> ReconnectingConnection<C, F>
+where
+    C: Connection,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<C, C::Error>>,
+{
+    /// Opens the initial connection with `connect`.
+    pub async fn connect(mut connect: F, policy: ReconnectPolicy) -> Result<Self, C::Error> {
+        let connection = connect().await?;
+
+        Ok(Self {
+            connection,
+            connect,
+            policy,
+        })
+    }
+
+    /// Returns a reference to the current underlying connection.
+    pub fn get_ref(&self) -> &C {
+        &self.connection
+    }
+
+    /// Returns a mutable reference to the current underlying connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.connection
+    }
+
+    /// Returns the current underlying connection.
+    pub fn into_inner(self) -> C {
+        self.connection
+    }
+
+    /// Replaces the underlying connection after it failed with `error`.
+    ///
+    /// Returns the last error if every attempt fails.
+    async fn reconnect(&mut self, error: C::Error) -> Result<(), C::Error> {
+        warn!("Connection lost: {}. Reconnecting...", error);
+
+        let mut last_error = error;
+        let mut delays = self.policy.backoff.delays();
+        for attempt in 1..=self.policy.max_attempts {
+            if let Some(delay) = delays.next() {
+                sleep(delay).await;
+            }
+
+            match (self.connect)().await {
+                Ok(mut connection) => {
+                    info!("Reconnected after {} attempt(s)", attempt);
+                    connection.set_timeout(self.connection.timeout());
+                    self.connection = connection;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(
+                        "Reconnect attempt {}/{} failed: {}",
+                        attempt, self.policy.max_attempts, e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+impl<C, F, Fut> Connection for ReconnectingConnection<C, F>
+where
+    C: Connection,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<C, C::Error>>,
+{
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection.connection_type()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.connection.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.connection.set_timeout(timeout);
+    }
+
+    fn partial_packet(&self) -> &[u8] {
+        self.connection.partial_packet()
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), C::Error> {
+        // Encode once so that the packet can be resent on the new connection.
+        let encoded = packet.encode()?;
+
+        match self.connection.send_packet(encoded.clone()).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.send_packet(encoded).await
+            }
+            result => result,
+        }
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, C::Error> {
+        match self.connection.receive_packet(timeout).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.receive_packet(timeout).await
+            }
+            result => result,
+        }
+    }
+
+    async fn send_packets(&mut self, packets: &[impl Encode]) -> Result<(), C::Error> {
+        // Forwarded so that the inner connection can split the batch however it needs to.
+        match self.connection.send_packets(packets).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.send_packets(packets).await
+            }
+            result => result,
+        }
+    }
+
+    async fn receive_packets<P: Decode>(
+        &mut self,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<P>, C::Error> {
+        match self.connection.receive_packets(count, timeout).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.receive_packets(count, timeout).await
+            }
+            result => result,
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, C::Error> {
+        match self.connection.read_user(buf).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.read_user(buf).await
+            }
+            result => result,
+        }
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, C::Error> {
+        match self.connection.write_user(buf).await {
+            Err(e) if e.is_disconnect() => {
+                self.reconnect(e).await?;
+                self.connection.write_user(buf).await
+            }
+            result => result,
+        }
+    }
+
+    async fn close(self) -> Result<(), C::Error> {
+        self.connection.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ReconnectPolicy, ReconnectingConnection};
+    use crate::connection::{
+        mock::{MockConnection, MockError},
+        Backoff, Connection, ConnectionType,
+    };
+    use crate::fixtures::{system_version_reply, SYSTEM_VERSION_REQUEST};
+    use crate::packets::system::{GetSystemVersionPacket, GetSystemVersionReplyPacket};
+
+    /// Hands out each of `connections` in turn, then fails to connect.
+    fn connector(
+        connections: Vec<MockConnection>,
+    ) -> impl FnMut() -> std::future::Ready<Result<MockConnection, MockError>> {
+        let mut connections = connections.into_iter();
+        move || std::future::ready(connections.next().ok_or(MockError::Disconnected))
+    }
+
+    fn policy(max_attempts: usize) -> ReconnectPolicy {
+        ReconnectPolicy::new()
+            .max_attempts(max_attempts)
+            .backoff(Backoff::none())
+    }
+
+    #[tokio::test]
+    async fn survives_drop() {
+        let mut dropped = MockConnection::new(ConnectionType::Bluetooth);
+        dropped.fail_sends_with_disconnect(1);
+        let mut reopened = MockConnection::new(ConnectionType::Bluetooth);
+        reopened
+            .expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+            .queue_reply(system_version_reply(1));
+
+        let mut connection =
+            ReconnectingConnection::connect(connector(vec![dropped, reopened]), policy(1))
+                .await
+                .unwrap();
+        connection.set_timeout(Duration::from_secs(3));
+
+        let reply = connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(
+                Duration::from_millis(100),
+                1,
+                GetSystemVersionPacket::new(()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(reply.payload.version.major, 1);
+        assert!(connection.get_ref().is_finished());
+        // The new connection keeps the timeout that was set on the old one.
+        assert_eq!(connection.timeout(), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn batch_survives_drop() {
+        let mut dropped = MockConnection::new(ConnectionType::Bluetooth);
+        dropped.fail_sends_with_disconnect(1);
+        let mut reopened = MockConnection::new(ConnectionType::Bluetooth);
+        reopened
+            .expect_request(SYSTEM_VERSION_REQUEST.repeat(2))
+            .queue_reply(system_version_reply(1))
+            .queue_reply(system_version_reply(2));
+
+        let mut connection =
+            ReconnectingConnection::connect(connector(vec![dropped, reopened]), policy(1))
+                .await
+                .unwrap();
+        connection
+            .send_packets(&[
+                GetSystemVersionPacket::new(()),
+                GetSystemVersionPacket::new(()),
+            ])
+            .await
+            .unwrap();
+        let replies = connection
+            .receive_packets::<GetSystemVersionReplyPacket>(2, Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        assert_eq!(replies[1].payload.version.major, 2);
+        assert!(connection.get_ref().is_finished());
+    }
+
+    #[tokio::test]
+    async fn gives_up() {
+        let mut dropped = MockConnection::new(ConnectionType::Bluetooth);
+        dropped.fail_sends_with_disconnect(1);
+        let mut attempts = 0;
+        let mut connect = connector(vec![dropped]);
+
+        let mut connection = ReconnectingConnection::connect(
+            || {
+                attempts += 1;
+                connect()
+            },
+            policy(2),
+        )
+        .await
+        .unwrap();
+        let result = connection
+            .send_packet(GetSystemVersionPacket::new(()))
+            .await;
+
+        assert!(matches!(result, Err(MockError::Disconnected)));
+        drop(connection);
+        // The initial connection, then each reconnect attempt.
+        assert_eq!(attempts, 3);
+    }
+}
@@ -1,6 +1,6 @@
 //! Implements discovering, opening, and interacting with vex devices connected over USB. This module does not have async support.
 
-use log::{debug, error, info, trace, warn};
+use log::{debug, info, trace, warn};
 use serialport::{SerialPortInfo, SerialPortType};
 use std::time::Duration;
 use thiserror::Error;
@@ -11,18 +11,16 @@ use tokio::{
 };
 use tokio_serial::SerialStream;
 
-use super::{Connection, ConnectionType};
+use super::{Connection, ConnectionError, ConnectionType, DEFAULT_TIMEOUT};
 use crate::{
-    connection::{trim_packets, RawPacket},
+    commands::CommandError,
+    connection::{
+        read_packet, read_user_fifo, take_packet, write_user_fifo, PacketDirection, PacketObserver,
+        RawPacket,
+    },
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
-    packets::{
-        cdc2::Cdc2Ack,
-        controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket},
-        HOST_BOUND_HEADER,
-    },
-    string::VarLengthString,
-    varint::VarU16,
+    packets::cdc2::Cdc2Ack,
 };
 
 /// The USB venddor ID for VEX devices
@@ -36,6 +34,9 @@ pub const V5_CONTROLLER_USB_PID: u16 = 0x0503;
 
 pub const V5_SERIAL_BAUDRATE: u32 = 115200;
 
+/// The default size of the buffer that user program output is read into.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
 /// The information of a generic vex serial port
 #[derive(Clone, Debug)]
 pub struct VexSerialPort {
@@ -189,7 +190,11 @@ fn types_by_name_order(ports: &[SerialPortInfo]) -> Option<Vec<VexSerialPort>> {
 }
 
 /// Finds all available VEX serial ports that can be connected to.
-fn find_ports() -> Result<Vec<VexSerialPort>, SerialError> {
+///
+/// Ports are filtered by the VEX USB vendor ID and classified as a brain's
+/// system or user port, or a controller port. Use [`find_devices`] to group
+/// these ports into devices.
+pub fn find_ports() -> Result<Vec<VexSerialPort>, SerialError> {
     // Get all available serial ports
     let ports = tokio_serial::available_ports()?;
 
@@ -329,109 +334,175 @@ impl SerialDevice {
     }
 }
 
-/// Decodes a [`HostBoundPacket`]'s header sequence.
-fn decode_header(data: impl IntoIterator<Item = u8>) -> Result<[u8; 2], DecodeError> {
-    let mut data = data.into_iter();
-    let header = Decode::decode(&mut data)?;
-    if header != HOST_BOUND_HEADER {
-        return Err(DecodeError::InvalidHeader);
-    }
-    Ok(header)
-}
-
 /// An open serial connection to a V5 device.
 #[derive(Debug)]
 pub struct SerialConnection {
     system_port: SerialStream,
     user_port: Option<BufReader<SerialStream>>,
     incoming_packets: Vec<RawPacket>,
+    partial_packet: Vec<u8>,
+    observer: PacketObserver,
+    timeout: Duration,
 }
 
-impl SerialConnection {
-    /// Opens a new serial connection to a V5 Brain.
-    pub fn open(device: SerialDevice, timeout: Duration) -> Result<Self, SerialError> {
-        // Open the system port
-        let system_port = match tokio_serial::SerialStream::open(
-            &tokio_serial::new(device.system_port(), 115200)
-                .parity(tokio_serial::Parity::None)
-                .timeout(timeout)
-                .stop_bits(tokio_serial::StopBits::One),
-        ) {
-            Ok(v) => Ok(v),
-            Err(e) => Err(SerialError::SerialportError(e)),
-        }?;
+/// Configures and opens a [`SerialConnection`].
+///
+/// A brain shows up as two serial ports: the system port, which carries packets, and the
+/// user port, which carries user program I/O. A controller only has a system port.
+/// Both default to the baud rate of [`V5_SERIAL_BAUDRATE`].
+///
+/// ```no_run
+/// # async fn example() -> Result<(), vex_v5_serial::connection::serial::SerialError> {
+/// use std::time::Duration;
+/// use vex_v5_serial::connection::serial::SerialConnectionBuilder;
+///
+/// let connection = SerialConnectionBuilder::new()
+///     .system_port("/dev/ttyACM0")
+///     .user_port("/dev/ttyACM1")
+///     .read_timeout(Duration::from_secs(1))
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerialConnectionBuilder {
+    system_port: Option<String>,
+    user_port: Option<String>,
+    baud_rate: u32,
+    read_timeout: Duration,
+    read_buffer_size: usize,
+}
+impl Default for SerialConnectionBuilder {
+    fn default() -> Self {
+        Self {
+            system_port: None,
+            user_port: None,
+            baud_rate: V5_SERIAL_BAUDRATE,
+            read_timeout: DEFAULT_TIMEOUT,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+        }
+    }
+}
+impl SerialConnectionBuilder {
+    /// Creates a builder with no ports chosen.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        // Open the user port (if it exists)
-        let user_port = if let Some(port) = &device.user_port() {
-            Some(match tokio_serial::SerialStream::open(
-                &tokio_serial::new(port, V5_SERIAL_BAUDRATE)
-                    .parity(tokio_serial::Parity::None)
-                    .timeout(timeout)
-                    .stop_bits(tokio_serial::StopBits::One),
-            ) {
-                Ok(v) => Ok(BufReader::new(v)),
-                Err(e) => Err(SerialError::SerialportError(e)),
-            }?)
-        } else {
-            None
-        };
+    /// Uses the ports of a discovered device.
+    pub fn device(mut self, device: &SerialDevice) -> Self {
+        self.system_port = Some(device.system_port());
+        self.user_port = device.user_port();
+        self
+    }
 
-        Ok(Self {
-            system_port,
-            user_port,
-            incoming_packets: Default::default(),
-        })
+    /// Sets the path of the system port, such as `/dev/ttyACM0` or `COM3`.
+    pub fn system_port(mut self, path: impl Into<String>) -> Self {
+        self.system_port = Some(path.into());
+        self
     }
 
-    /// Receives a single packet from the serial port and adds it to the queue of incoming packets.
-    async fn receive_one_packet(&mut self) -> Result<(), SerialError> {
-        // Read the header into an array
-        let mut header = [0u8; 2];
-        self.system_port.read_exact(&mut header).await?;
-
-        // Verify that the header is valid
-        if let Err(e) = decode_header(header) {
-            warn!(
-                "Skipping packet with invalid header: {:x?}. Error: {}",
-                header, e
-            );
-            return Ok(());
-        }
+    /// Sets the path of the user port.
+    ///
+    /// Without one, user program I/O goes through the system port as it does on a controller.
+    pub fn user_port(mut self, path: impl Into<String>) -> Self {
+        self.user_port = Some(path.into());
+        self
+    }
 
-        // Create a buffer to store the entire packet
-        let mut packet = Vec::from(header);
+    /// Sets the baud rate of both ports.
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
 
-        // Push the command's ID
-        packet.push(self.system_port.read_u8().await?);
+    /// Sets how long a single read from either port may block.
+    ///
+    /// This is separate from how long commands wait for a reply, which is set with
+    /// [`Connection::set_timeout`].
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
 
-        // Get the size of the packet
-        // We do some extra logic to make sure we only read the necessary amount of bytes
-        let first_size_byte = self.system_port.read_u8().await?;
-        let size = if VarU16::check_wide(first_size_byte) {
-            let second_size_byte = self.system_port.read_u8().await?;
-            packet.extend([first_size_byte, second_size_byte]);
+    /// Sets the size of the buffer that user program output is read into, which defaults to
+    /// [`DEFAULT_READ_BUFFER_SIZE`].
+    ///
+    /// A larger buffer drains more output from the port per read, which helps keep up with
+    /// programs that stream a lot of data, at the cost of memory. Reads still return as soon
+    /// as any output is available. Output is only buffered from the user port, so this has no
+    /// effect without one.
+    pub fn read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = read_buffer_size;
+        self
+    }
 
-            // Decode the size of the packet
-            VarU16::decode(vec![first_size_byte, second_size_byte])?
-        } else {
-            packet.push(first_size_byte);
+    /// Opens the configured ports.
+    pub async fn build(self) -> Result<SerialConnection, SerialError> {
+        self.open()
+    }
 
-            // Decode the size of the packet
-            VarU16::decode(vec![first_size_byte])?
-        }
-        .into_inner() as usize;
+    fn open(self) -> Result<SerialConnection, SerialError> {
+        let system_port = self.system_port.ok_or(SerialError::MissingSystemPort)?;
+        let open_port = |path: &str| {
+            tokio_serial::SerialStream::open(
+                &tokio_serial::new(path, self.baud_rate)
+                    .parity(tokio_serial::Parity::None)
+                    .timeout(self.read_timeout)
+                    .stop_bits(tokio_serial::StopBits::One),
+            )
+        };
 
-        // Read the rest of the packet
-        let mut payload = vec![0; size];
-        self.system_port.read_exact(&mut payload).await?;
+        // Open the system port
+        let system_port = open_port(&system_port)?;
+
+        // Open the user port (if it exists)
+        let user_port = match &self.user_port {
+            Some(port) => Some(BufReader::with_capacity(
+                self.read_buffer_size,
+                open_port(port)?,
+            )),
+            None => None,
+        };
+
+        Ok(SerialConnection {
+            system_port,
+            user_port,
+            incoming_packets: Default::default(),
+            partial_packet: Vec::new(),
+            observer: Default::default(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+}
 
-        // Completely fill the packet
-        packet.extend(payload);
+impl SerialConnection {
+    /// Opens a new serial connection to a V5 Brain.
+    ///
+    /// Use a [`SerialConnectionBuilder`] for more control over how the ports are opened.
+    pub fn open(device: SerialDevice, timeout: Duration) -> Result<Self, SerialError> {
+        SerialConnectionBuilder::new()
+            .device(&device)
+            .read_timeout(timeout)
+            .open()
+    }
 
-        debug!("received packet: {:x?}", packet);
+    /// Sets a callback that is given the raw bytes of every packet sent and received.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: impl Fn(PacketDirection, &[u8]) + Send + Sync + 'static,
+    ) {
+        self.observer.set(observer);
+    }
 
-        // Push the packet to the incoming packets buffer
-        self.incoming_packets.push(RawPacket::new(packet));
+    /// Receives a single packet from the serial port and adds it to the queue of incoming packets.
+    async fn receive_one_packet(&mut self) -> Result<(), SerialError> {
+        let packet =
+            read_packet::<_, SerialError>(&mut self.system_port, &mut self.partial_packet).await?;
+        self.observer
+            .observe(PacketDirection::Received, &packet.bytes);
+        self.incoming_packets.push(packet);
 
         Ok(())
     }
@@ -448,11 +519,24 @@ impl Connection for SerialConnection {
         }
     }
 
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn partial_packet(&self) -> &[u8] {
+        &self.partial_packet
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), SerialError> {
         // Encode the packet
         let encoded = packet.encode()?;
 
         trace!("Sending packet: {:x?}", encoded);
+        self.observer.observe(PacketDirection::Sent, &encoded);
 
         // Write the packet to the serial port
         match self.system_port.write_all(&encoded).await {
@@ -473,13 +557,9 @@ impl Connection for SerialConnection {
         select! {
             result = async {
                 loop {
-                    for packet in self.incoming_packets.iter_mut() {
-                        if let Ok(decoded) = packet.decode_and_use::<P>() {
-                            trim_packets(&mut self.incoming_packets);
-                            return Ok(decoded);
-                        }
+                    if let Some(decoded) = take_packet::<P>(&mut self.incoming_packets) {
+                        return Ok(decoded?);
                     }
-                    trim_packets(&mut self.incoming_packets);
                     self.receive_one_packet().await?;
                 }
             } => result,
@@ -491,58 +571,25 @@ impl Connection for SerialConnection {
         if let Some(user_port) = &mut self.user_port {
             Ok(user_port.read(buf).await?)
         } else {
-            let mut data = Vec::new();
-            loop {
-                let fifo = self
-                    .packet_handshake::<UserFifoReplyPacket>(
-                        Duration::from_millis(100),
-                        1,
-                        UserFifoPacket::new(UserFifoPayload {
-                            channel: 1, // stdio channel
-                            write: None,
-                        }),
-                    )
-                    .await?
-                    .try_into_inner()?;
-                if let Some(read) = fifo.data {
-                    data.extend(read.0.as_bytes());
-                    break;
-                }
-            }
-
-            let len = data.len().min(buf.len());
-            buf[..len].copy_from_slice(&data[..len]);
-
-            Ok(len)
+            read_user_fifo(self, buf).await
         }
     }
 
-    async fn write_user(&mut self, mut buf: &[u8]) -> Result<usize, SerialError> {
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, SerialError> {
         if let Some(user_port) = &mut self.user_port {
             Ok(user_port.write(buf).await?)
         } else {
-            let buf_len = buf.len();
-            while !buf.is_empty() {
-                let (chunk, rest) = buf.split_at(std::cmp::min(224, buf.len()));
-                _ = self
-                    .packet_handshake::<UserFifoReplyPacket>(
-                        Duration::from_millis(100),
-                        1,
-                        UserFifoPacket::new(UserFifoPayload {
-                            channel: 1, // stdio channel
-                            write: Some(
-                                VarLengthString::new(String::from_utf8(chunk.to_vec()).unwrap())
-                                    .unwrap(),
-                            ),
-                        }),
-                    )
-                    .await?
-                    .try_into_inner()?;
-                buf = rest;
-            }
+            write_user_fifo(self, buf).await
+        }
+    }
 
-            Ok(buf_len)
+    async fn close(mut self) -> Result<(), SerialError> {
+        self.system_port.flush().await?;
+        if let Some(user_port) = &mut self.user_port {
+            user_port.flush().await?;
         }
+
+        Ok(())
     }
 }
 
@@ -556,10 +603,26 @@ pub enum SerialError {
     DecodeError(#[from] DecodeError),
     #[error("Packet timeout")]
     Timeout,
-    #[error("NACK received: {0:?}")]
+    #[error("NACK received: {0}")]
     Nack(#[from] Cdc2Ack),
-    #[error("Serialport Error")]
+    #[error("Command failed: {0}")]
+    Command(#[from] CommandError),
+    #[error("Serialport Error: {0}")]
     SerialportError(#[from] tokio_serial::Error),
+    #[error("No system port was given to open")]
+    MissingSystemPort,
     #[error("Could not infer serial port types")]
     CouldntInferTypes,
 }
+impl ConnectionError for SerialError {
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::IoError(_))
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::DecodeError(e) => e.is_corrupted(),
+            _ => matches!(self, Self::IoError(_) | Self::Timeout),
+        }
+    }
+}
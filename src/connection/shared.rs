@@ -0,0 +1,173 @@
+//! Shares one [`Connection`] between many tasks by running their commands one at a time.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use tokio::sync::{mpsc, oneshot};
+
+use super::Connection;
+use crate::commands::{Command, CommandError};
+
+/// A command waiting for its turn on the connection.
+type Job<C> = Box<dyn for<'a> FnOnce(&'a mut C) -> Pin<Box<dyn Future<Output = ()> + 'a>> + Send>;
+
+/// A cloneable handle that queues commands to run on a connection owned by a
+/// [`ConnectionWorker`].
+///
+/// Commands from every handle are run in the order they were queued, and each one finishes
+/// before the next starts, so the packets of two commands are never interleaved on the wire.
+///
+/// ```no_run
+/// # async fn example(connection: vex_v5_serial::connection::mock::MockConnection) {
+/// use vex_v5_serial::{commands::system::GetDeviceId, connection::shared::SharedConnection};
+///
+/// let (shared, worker) = SharedConnection::new(connection);
+///
+/// // Handles can be moved into other tasks.
+/// let handle = shared.clone();
+/// let task = tokio::spawn(async move { handle.execute_command(GetDeviceId).await });
+///
+/// // The worker stops once every handle is gone.
+/// drop(shared);
+/// let (connection, id) = tokio::join!(worker.run(), task);
+/// # }
+/// ```
+pub struct SharedConnection<C> {
+    jobs: mpsc::UnboundedSender<Job<C>>,
+}
+
+impl<C> Clone for SharedConnection<C> {
+    fn clone(&self) -> Self {
+        Self {
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<C: Connection + 'static> SharedConnection<C> {
+    /// Hands `connection` over to a worker and returns the first handle to it.
+    ///
+    /// Nothing is sent until the worker is [run](ConnectionWorker::run).
+    pub fn new(connection: C) -> (Self, ConnectionWorker<C>) {
+        let (jobs, queue) = mpsc::unbounded_channel();
+
+        (Self { jobs }, ConnectionWorker { connection, queue })
+    }
+
+    /// Queues a command and returns a future that resolves to its output.
+    ///
+    /// The command is queued straight away, so it keeps its place even if the returned
+    /// future isn't polled until later. Fails with [`CommandError::ConnectionStopped`] if the
+    /// worker is dropped before the command finishes.
+    pub fn execute_command<T>(
+        &self,
+        mut command: T,
+    ) -> impl Future<Output = Result<T::Output, C::Error>> + Send + 'static
+    where
+        T: Command + Send + 'static,
+        T::Output: Send + 'static,
+        C::Error: Send + 'static,
+    {
+        let (reply, output) = oneshot::channel();
+        let job: Job<C> = Box::new(move |connection| {
+            Box::pin(async move {
+                let _ = reply.send(command.execute(connection).await);
+            })
+        });
+        // If the worker is gone, the job and its reply sender are dropped here.
+        let _ = self.jobs.send(job);
+
+        async move {
+            output
+                .await
+                .unwrap_or_else(|_| Err(CommandError::ConnectionStopped.into()))
+        }
+    }
+}
+
+/// Owns the connection behind a [`SharedConnection`] and runs its queued commands.
+pub struct ConnectionWorker<C> {
+    connection: C,
+    queue: mpsc::UnboundedReceiver<Job<C>>,
+}
+
+impl<C: Connection + 'static> ConnectionWorker<C> {
+    /// Runs queued commands until every [`SharedConnection`] handle has been dropped, then
+    /// returns the connection.
+    ///
+    /// Commands are generic over the connection, so this future isn't [`Send`]. Run it
+    /// alongside the tasks using the connection with `tokio::join!` or on a
+    /// `tokio::task::LocalSet`.
+    pub async fn run(mut self) -> C {
+        while let Some(job) = self.queue.recv().await {
+            job(&mut self.connection).await;
+        }
+
+        self.connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SharedConnection;
+    use crate::commands::{Command, CommandError};
+    use crate::connection::{
+        mock::{MockConnection, MockError},
+        Connection, ConnectionType,
+    };
+    use crate::fixtures::{system_version_reply, SYSTEM_VERSION_REQUEST};
+    use crate::packets::system::{GetSystemVersionPacket, GetSystemVersionReplyPacket};
+
+    struct GetMajorVersion;
+    impl Command for GetMajorVersion {
+        type Output = u8;
+
+        async fn execute<C: Connection + ?Sized>(
+            &mut self,
+            connection: &mut C,
+        ) -> Result<Self::Output, C::Error> {
+            let reply = connection
+                .packet_handshake::<GetSystemVersionReplyPacket>(
+                    Duration::from_millis(100),
+                    1,
+                    GetSystemVersionPacket::new(()),
+                )
+                .await?;
+
+            Ok(reply.payload.version.major)
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_in_order() {
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        for major in [1, 2] {
+            mock.expect_request(SYSTEM_VERSION_REQUEST.to_vec())
+                .queue_reply(system_version_reply(major));
+        }
+
+        let (shared, worker) = SharedConnection::new(mock);
+        let first = shared.execute_command(GetMajorVersion);
+        let second = shared.clone().execute_command(GetMajorVersion);
+        drop(shared);
+
+        let connection = worker.run().await;
+        assert_eq!(first.await.unwrap(), 1);
+        assert_eq!(second.await.unwrap(), 2);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn worker_stopped() {
+        let (shared, worker) = SharedConnection::new(MockConnection::new(ConnectionType::Wired));
+        drop(worker);
+
+        let result = shared.execute_command(GetMajorVersion).await;
+        assert!(matches!(
+            result,
+            Err(MockError::Command(CommandError::ConnectionStopped))
+        ));
+    }
+}
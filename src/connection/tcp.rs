@@ -0,0 +1,272 @@
+//! Implements interacting with V5 devices whose system port is relayed over a TCP socket.
+//!
+//! This is useful for bridges such as `socat` and headless simulators. The socket carries the
+//! same packet stream as a serial system port, so user program I/O goes through the user FIFO
+//! packets rather than a dedicated user port.
+
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::trace;
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, net::TcpStream, select, time::sleep};
+
+use super::{Connection, ConnectionError, ConnectionType, DEFAULT_TIMEOUT};
+use crate::{
+    commands::CommandError,
+    connection::{
+        read_packet, read_user_fifo, take_packet, write_user_fifo, PacketDirection, PacketObserver,
+        RawPacket,
+    },
+    decode::{Decode, DecodeError},
+    encode::{Encode, EncodeError},
+    packets::cdc2::Cdc2Ack,
+};
+
+/// An open TCP connection to a relayed V5 system port.
+#[derive(Debug)]
+pub struct TcpConnection {
+    stream: TcpStream,
+    incoming_packets: Vec<RawPacket>,
+    partial_packet: Vec<u8>,
+    observer: PacketObserver,
+    timeout: Duration,
+}
+
+impl TcpConnection {
+    /// Connects to a V5 system port relayed at the given address.
+    pub async fn connect(addr: SocketAddr) -> Result<Self, TcpError> {
+        let stream = match TcpStream::connect(addr).await {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                return Err(TcpError::NotConnected(addr))
+            }
+            Err(e) => return Err(TcpError::IoError(e)),
+        };
+
+        // Packets are small and latency sensitive, so don't let them be batched.
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            incoming_packets: Vec::new(),
+            partial_packet: Vec::new(),
+            observer: PacketObserver::default(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets a callback that is given the raw bytes of every packet sent and received.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: impl Fn(PacketDirection, &[u8]) + Send + Sync + 'static,
+    ) {
+        self.observer.set(observer);
+    }
+
+    /// Receives a single packet from the socket and adds it to the queue of incoming packets.
+    async fn receive_one_packet(&mut self) -> Result<(), TcpError> {
+        let packet = read_packet::<_, TcpError>(&mut self.stream, &mut self.partial_packet).await?;
+        self.observer
+            .observe(PacketDirection::Received, &packet.bytes);
+        self.incoming_packets.push(packet);
+
+        Ok(())
+    }
+}
+
+impl Connection for TcpConnection {
+    type Error = TcpError;
+
+    fn connection_type(&self) -> ConnectionType {
+        ConnectionType::Wired
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn partial_packet(&self) -> &[u8] {
+        &self.partial_packet
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), TcpError> {
+        // Encode the packet
+        let encoded = packet.encode()?;
+
+        trace!("Sending packet: {:x?}", encoded);
+        self.observer.observe(PacketDirection::Sent, &encoded);
+
+        // Write the packet to the socket
+        self.stream.write_all(&encoded).await?;
+        self.stream.flush().await?;
+
+        Ok(())
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, TcpError> {
+        // Return an error if the right packet is not received within the timeout
+        select! {
+            result = async {
+                loop {
+                    if let Some(decoded) = take_packet::<P>(&mut self.incoming_packets) {
+                        return Ok(decoded?);
+                    }
+                    self.receive_one_packet().await?;
+                }
+            } => result,
+            _ = sleep(timeout) => Err(TcpError::Timeout)
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, TcpError> {
+        read_user_fifo(self, buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, TcpError> {
+        write_user_fifo(self, buf).await
+    }
+
+    async fn close(mut self) -> Result<(), TcpError> {
+        self.stream.shutdown().await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TcpError {
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Packet timeout")]
+    Timeout,
+    #[error("NACK received: {0}")]
+    Nack(#[from] Cdc2Ack),
+    #[error("Command failed: {0}")]
+    Command(#[from] CommandError),
+    #[error("Nothing is listening at {0}")]
+    NotConnected(SocketAddr),
+}
+impl ConnectionError for TcpError {
+    fn is_disconnect(&self) -> bool {
+        matches!(self, Self::IoError(_) | Self::NotConnected(_))
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::DecodeError(e) => e.is_corrupted(),
+            _ => matches!(self, Self::IoError(_) | Self::Timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{TcpConnection, TcpError};
+    use crate::connection::{Connection, ConnectionError};
+    use crate::fixtures::{
+        cdc2_reply, read_file_reply, system_version_reply, SYSTEM_VERSION_REQUEST,
+    };
+    use crate::packets::{
+        file::ReadFileReplyPacket,
+        system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
+    };
+
+    #[tokio::test]
+    async fn connect_refused() {
+        // Find a free port, then stop listening on it.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let result = TcpConnection::connect(addr).await;
+        assert!(matches!(result, Err(TcpError::NotConnected(refused)) if refused == addr));
+    }
+
+    #[tokio::test]
+    async fn handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let brain = async {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; SYSTEM_VERSION_REQUEST.len()];
+            socket.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, SYSTEM_VERSION_REQUEST);
+            socket.write_all(&system_version_reply(1)).await.unwrap();
+            socket
+        };
+        let host = async {
+            let mut connection = TcpConnection::connect(addr).await.unwrap();
+            connection
+                .packet_handshake::<GetSystemVersionReplyPacket>(
+                    Duration::from_secs(1),
+                    1,
+                    GetSystemVersionPacket::new(()),
+                )
+                .await
+        };
+
+        let (_socket, reply) = tokio::join!(brain, host);
+        assert_eq!(reply.unwrap().payload.version.major, 1);
+    }
+
+    #[tokio::test]
+    async fn skips_other_cdc2_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let brain = async {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // A late duplicate of the reply that opened the transfer arrives first.
+            let mut replies = cdc2_reply(0x11, 0x76, &[0; 10]);
+            replies.extend(read_file_reply(0x0380_0000, &[1, 2, 3, 4]));
+            socket.write_all(&replies).await.unwrap();
+            socket
+        };
+        let host = async {
+            let mut connection = TcpConnection::connect(addr).await.unwrap();
+            connection
+                .receive_packet::<ReadFileReplyPacket>(Duration::from_secs(1))
+                .await
+        };
+
+        let (_socket, reply) = tokio::join!(brain, host);
+        let (address, _) = reply.unwrap().payload.unwrap().unwrap();
+        assert_eq!(address, 0x0380_0000);
+    }
+
+    #[tokio::test]
+    async fn eof_is_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The relay closes the socket straight away.
+        let (accepted, connection) = tokio::join!(listener.accept(), TcpConnection::connect(addr));
+        drop(accepted.unwrap());
+
+        let result = connection
+            .unwrap()
+            .receive_packet::<GetSystemVersionReplyPacket>(Duration::from_secs(1))
+            .await;
+        assert!(result.is_err_and(|e| e.is_disconnect()));
+    }
+}
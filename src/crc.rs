@@ -16,3 +16,44 @@ pub const VEX_CRC32: crc::Crc<u32> = Crc::<u32>::new(&crc::Algorithm {
     residue: 0x00000000,
     width: 32,
 });
+
+/// Computes the CRC16 that ends every CDC2 packet.
+///
+/// This is CRC16/XMODEM: the CCITT polynomial `0x1021` with a seed of zero.
+/// It covers every byte of the packet before it, header included.
+pub fn crc16(data: &[u8]) -> u16 {
+    VEX_CRC16.checksum(data)
+}
+
+/// Computes the CRC32 that file transfers are checked with.
+pub fn crc32(data: &[u8]) -> u32 {
+    VEX_CRC32.checksum(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc16, crc32};
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc32(b"123456789"), 0x89A1897F);
+    }
+
+    #[test]
+    fn packet_residue() {
+        // Hand-built packets with each header. Any packet followed by its big-endian CRC16
+        // has a CRC of zero, so this doesn't depend on knowing the right CRC up front.
+        let packets: [&[u8]; 4] = [
+            &[0xC9, 0x36, 0xB8, 0x47, 0x56, 0x20, 0x00],
+            &[0xC9, 0x36, 0xB8, 0x47, 0x56, 0x2B, 0x02, 0x00, 0x00],
+            &[0xAA, 0x55, 0x56, 0x04, 0x19, 0xD3],
+            &[0xAA, 0x55, 0x56, 0x08, 0x19, 0x76, 0x01, 0x00, 0x00, 0x00],
+        ];
+        for packet in packets {
+            let mut packet = packet.to_vec();
+            packet.extend(crc16(&packet).to_be_bytes());
+            assert_eq!(crc16(&packet), 0);
+        }
+    }
+}
@@ -1,10 +1,18 @@
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
+#[cfg(feature = "derive")]
+pub use vex_v5_serial_derive::Decode;
+
 #[derive(Error, Debug)]
 pub enum DecodeError {
     #[error("Packet too short")]
     PacketTooShort,
+    /// A length field promised more bytes than the packet actually contains.
+    #[error(
+        "Unexpected end of packet. Needed {needed} bytes, but only {available} were available"
+    )]
+    UnexpectedEof { needed: usize, available: usize },
     #[error("Invalid response header")]
     InvalidHeader,
     #[error("String ran past expected nul terminator")]
@@ -13,11 +21,120 @@ pub enum DecodeError {
     InvalidStringContents(#[from] FromUtf8Error),
     #[error("Could not decode byte with unexpected value. Found {value:x}, expected one of: {expected:x?}")]
     UnexpectedValue { value: u8, expected: &'static [u8] },
+    /// A byte didn't match any variant of an enum, such as a code added by newer firmware.
+    #[error("Unknown {type_name} {value:#04x}")]
+    UnknownDiscriminant { type_name: &'static str, value: u8 },
+    /// The packet is a reply to a different command, such as a late reply to an earlier one.
+    ///
+    /// For CDC2 packets, this is also returned when the extended command IDs don't match.
+    #[error("Expected a reply to command {expected:#04x}, found one to {found:#04x}")]
+    UnexpectedCommandId { expected: u8, found: u8 },
+    #[error("Packet CRC mismatch. Expected {expected:#06x}, found {found:#06x}")]
+    BadCrc { expected: u16, found: u16 },
     #[error("Attempted to decode a choice, but neither choice was successful: left: {left}, right: {right}")]
     BothChoicesFailed {
         left: Box<DecodeError>,
         right: Box<DecodeError>,
     },
+    /// An error from decoding a field of a larger value.
+    ///
+    /// `path` names the field, with nested fields separated by dots, and `offset` is the byte
+    /// the innermost field starts at.
+    #[error("At byte {offset} while decoding {path}: {source}")]
+    Context {
+        path: String,
+        offset: usize,
+        source: Box<DecodeError>,
+    },
+}
+impl DecodeError {
+    /// Whether the bytes were a different packet, rather than a malformed one of the type being
+    /// decoded.
+    pub fn is_other_packet(&self) -> bool {
+        match self {
+            Self::InvalidHeader | Self::UnexpectedCommandId { .. } => true,
+            Self::BothChoicesFailed { left, right } => {
+                left.is_other_packet() && right.is_other_packet()
+            }
+            Self::Context { source, .. } => source.is_other_packet(),
+            _ => false,
+        }
+    }
+
+    /// Whether the packet was cut short or garbled on the way, so asking for it again could
+    /// succeed.
+    pub fn is_corrupted(&self) -> bool {
+        match self {
+            Self::PacketTooShort | Self::UnexpectedEof { .. } | Self::BadCrc { .. } => true,
+            Self::BothChoicesFailed { left, right } => left.is_corrupted() || right.is_corrupted(),
+            Self::Context { source, .. } => source.is_corrupted(),
+            _ => false,
+        }
+    }
+
+    /// Marks this as an error from decoding `field`, which started at byte `offset`.
+    ///
+    /// If this error already has context from a field nested inside `field`, the two are
+    /// merged into a single path.
+    pub fn context(self, field: &str, offset: usize) -> Self {
+        match self {
+            Self::Context {
+                path,
+                offset: inner,
+                source,
+            } => Self::Context {
+                // Indices read better without a dot, as in `slots[2]`.
+                path: if path.starts_with('[') {
+                    format!("{field}{path}")
+                } else {
+                    format!("{field}.{path}")
+                },
+                offset: offset + inner,
+                source,
+            },
+            error => Self::Context {
+                path: field.to_string(),
+                offset,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The error without any [`DecodeError::Context`] around it.
+    pub fn root_cause(&self) -> &Self {
+        match self {
+            Self::Context { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+}
+
+/// An iterator that counts how many bytes have been taken from it.
+///
+/// This lets decoders report where a field started with [`DecodeError::context`].
+#[derive(Debug, Clone)]
+pub struct Counted<I> {
+    inner: I,
+    position: usize,
+}
+impl<I: Iterator<Item = u8>> Counted<I> {
+    pub fn new(inner: I) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    /// How many bytes have been taken so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+impl<I: Iterator<Item = u8>> Iterator for Counted<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.inner.next()?;
+        self.position += 1;
+        Some(byte)
+    }
 }
 
 pub trait Decode {
@@ -31,6 +148,56 @@ pub trait SizedDecode {
         Self: Sized;
 }
 
+/// Decodes a value that borrows from the bytes it was decoded from.
+///
+/// [`Decode`] copies everything it reads, which adds up when parsing many packets
+/// with large payloads. This decodes from a byte slice instead and advances it past
+/// the bytes that were used, so raw data and strings can be returned without copying.
+/// Every [`Decode`] type is also `DecodeRef`, so borrowed and owned fields can be mixed.
+pub trait DecodeRef<'a>: Sized {
+    fn decode_ref(data: &mut &'a [u8]) -> Result<Self, DecodeError>;
+}
+
+impl<'a, T: Decode> DecodeRef<'a> for T {
+    fn decode_ref(data: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        let mut consumed = 0;
+        let decoded = T::decode(data.iter().inspect(|_| consumed += 1).copied())?;
+        *data = &data[consumed..];
+        Ok(decoded)
+    }
+}
+impl<'a, const N: usize> DecodeRef<'a> for &'a [u8; N] {
+    fn decode_ref(data: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        let (array, rest) = data.split_first_chunk().ok_or(DecodeError::UnexpectedEof {
+            needed: N,
+            available: data.len(),
+        })?;
+        *data = rest;
+        Ok(array)
+    }
+}
+/// Borrows all of the remaining bytes.
+impl<'a> DecodeRef<'a> for &'a [u8] {
+    fn decode_ref(data: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        Ok(std::mem::take(data))
+    }
+}
+/// Borrows a null-terminated string, consuming the terminator.
+impl<'a> DecodeRef<'a> for &'a str {
+    fn decode_ref(data: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        let len = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(DecodeError::UnterminatedString)?;
+        let (bytes, rest) = data.split_at(len);
+        // Only copy the bytes if they need to be reported as invalid.
+        let string = std::str::from_utf8(bytes)
+            .map_err(|_| String::from_utf8(bytes.to_vec()).unwrap_err())?;
+        *data = &rest[1..];
+        Ok(string)
+    }
+}
+
 impl<T: Decode> SizedDecode for T {
     fn sized_decode(data: impl IntoIterator<Item = u8>, _: u16) -> Result<Self, DecodeError>
     where
@@ -109,3 +276,126 @@ impl<D: Decode + Default, const N: usize> Decode for [D; N] {
         Ok(decoded_array)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_ref() {
+        let data = [0x34, 0x12, b'h', b'i', 0x00, 0x01, 0x02, 0x03];
+        let mut buf = &data[..];
+
+        assert_eq!(u16::decode_ref(&mut buf).unwrap(), 0x1234);
+        assert_eq!(<&str>::decode_ref(&mut buf).unwrap(), "hi");
+        assert_eq!(<&[u8; 1]>::decode_ref(&mut buf).unwrap(), &[0x01]);
+        assert!(matches!(
+            <&[u8; 4]>::decode_ref(&mut buf),
+            Err(DecodeError::UnexpectedEof {
+                needed: 4,
+                available: 2
+            })
+        ));
+        assert_eq!(<&[u8]>::decode_ref(&mut buf).unwrap(), &[0x02, 0x03]);
+        assert!(buf.is_empty());
+
+        // The string borrows straight from the input.
+        let mut buf = &data[2..];
+        let string = <&str>::decode_ref(&mut buf).unwrap();
+        assert_eq!(string.as_ptr(), data[2..].as_ptr());
+    }
+
+    #[test]
+    fn context() {
+        let error = DecodeError::UnterminatedString
+            .context("name", 3)
+            .context("[1]", 4)
+            .context("slots", 1)
+            .context("payload", 6);
+
+        let DecodeError::Context { path, offset, .. } = &error else {
+            panic!("Expected context, found {error:?}");
+        };
+        assert_eq!(path, "payload.slots[1].name");
+        assert_eq!(*offset, 14);
+        assert!(matches!(
+            error.root_cause(),
+            DecodeError::UnterminatedString
+        ));
+    }
+
+    #[test]
+    fn other_packets() {
+        let wrong_id = DecodeError::UnexpectedCommandId {
+            expected: 0x19,
+            found: 0x13,
+        };
+        assert!(wrong_id.is_other_packet());
+        assert!(!DecodeError::BadCrc {
+            expected: 0,
+            found: 1
+        }
+        .is_other_packet());
+        assert!(!DecodeError::PacketTooShort
+            .context("payload", 6)
+            .is_other_packet());
+    }
+
+    #[test]
+    fn corrupted_packets() {
+        assert!(DecodeError::BadCrc {
+            expected: 0,
+            found: 1
+        }
+        .is_corrupted());
+        assert!(DecodeError::UnexpectedEof {
+            needed: 4,
+            available: 2
+        }
+        .context("payload", 6)
+        .is_corrupted());
+        assert!(!DecodeError::UnterminatedString.is_corrupted());
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(Decode, Debug, PartialEq)]
+    struct Reply {
+        id: u16,
+        count: u8,
+        #[vex(len = "count")]
+        items: Vec<u16>,
+        #[vex(skip)]
+        cached: bool,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_decode() {
+        let mut data = vec![0x34, 0x12, 0x02, 0x01, 0x00, 0x02, 0x00, 0xFF].into_iter();
+        let reply = Reply::decode(&mut data).unwrap();
+        assert_eq!(
+            reply,
+            Reply {
+                id: 0x1234,
+                count: 2,
+                items: vec![1, 2],
+                cached: false,
+            }
+        );
+
+        // The trailing byte is left for the caller
+        assert_eq!(data.len(), 1);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_decode_context() {
+        // The second item is missing.
+        let error = Reply::decode([0x34, 0x12, 0x02, 0x01, 0x00]).unwrap_err();
+        let DecodeError::Context { path, offset, .. } = &error else {
+            panic!("Expected context, found {error:?}");
+        };
+        assert_eq!(path, "items[1]");
+        assert_eq!(*offset, 5);
+    }
+}
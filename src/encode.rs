@@ -1,17 +1,35 @@
 use thiserror::Error;
 
+#[cfg(feature = "derive")]
+pub use vex_v5_serial_derive::Encode;
+
 #[derive(Error, Debug)]
 pub enum EncodeError {
-    #[error("String bytes are too long")]
-    StringTooLong,
+    #[error("String is {len} bytes, maximum is {max}")]
+    StringTooLong { len: usize, max: usize },
     #[error("Value too large for variable length u16")]
     VarShortTooLarge,
+    #[error("Payload is {len} bytes, maximum is {max}")]
+    PayloadTooLarge { len: usize, max: usize },
+    #[error("String contained invalid UTF-8: {0}")]
+    InvalidStringContents(#[from] std::str::Utf8Error),
 }
 
 /// A trait that allows for encoding a structure into a byte sequence.
+///
+/// Implementors must provide at least one of [`Encode::encode`] or [`Encode::encode_into`].
 pub trait Encode {
     /// Encodes a structure into a byte sequence.
-    fn encode(&self) -> Result<Vec<u8>, EncodeError>;
+    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut encoded = Vec::new();
+        self.encode_into(&mut encoded)?;
+        Ok(encoded)
+    }
+    /// Encodes a structure onto the end of an existing buffer.
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        buf.extend(self.encode()?);
+        Ok(())
+    }
     fn into_encoded(self) -> Result<Vec<u8>, EncodeError>
     where
         Self: Sized,
@@ -28,4 +46,89 @@ impl Encode for Vec<u8> {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         Ok(self.clone())
     }
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        buf.extend_from_slice(self);
+        Ok(())
+    }
+}
+impl Encode for &[u8] {
+    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        Ok(self.to_vec())
+    }
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        buf.extend_from_slice(self);
+        Ok(())
+    }
+}
+impl<const N: usize> Encode for [u8; N] {
+    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        Ok(self.to_vec())
+    }
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        buf.extend_from_slice(self);
+        Ok(())
+    }
+}
+
+macro_rules! impl_encode_le {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+// The V5 protocol is little-endian.
+impl_encode_le!(u8, i8, u16, i16, u32, i32, f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn little_endian_primitives() {
+        assert_eq!(0x12u8.encode().unwrap(), [0x12]);
+        assert_eq!((-2i8).encode().unwrap(), [0xFE]);
+        assert_eq!(0x1234u16.encode().unwrap(), [0x34, 0x12]);
+        assert_eq!((-2i16).encode().unwrap(), [0xFE, 0xFF]);
+        assert_eq!(0x12345678u32.encode().unwrap(), [0x78, 0x56, 0x34, 0x12]);
+        assert_eq!((-2i32).encode().unwrap(), [0xFE, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(1.0f32.encode().unwrap(), [0x00, 0x00, 0x80, 0x3F]);
+    }
+
+    #[test]
+    fn borrowed_bytes() {
+        let mut buf = vec![0x01];
+        [0x02, 0x03].encode_into(&mut buf).unwrap();
+        (&[0x04][..]).encode_into(&mut buf).unwrap();
+        assert_eq!(buf, [0x01, 0x02, 0x03, 0x04]);
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod derive_tests {
+    use super::*;
+    use crate::string::FixedLengthString;
+
+    #[derive(Encode)]
+    struct Payload {
+        name: FixedLengthString<4>,
+        #[vex(skip)]
+        _cached_len: usize,
+        data: Vec<u8>,
+    }
+
+    #[test]
+    fn derived_encode() {
+        let payload = Payload {
+            name: FixedLengthString::new("ab".to_string()).unwrap(),
+            _cached_len: 2,
+            data: vec![0xAA],
+        };
+        assert_eq!(payload.encode().unwrap(), [b'a', b'b', 0, 0, 0, 0xAA]);
+    }
 }
@@ -0,0 +1,30 @@
+//! Packets shared between the tests of several modules.
+
+/// An encoded [`GetSystemVersionPacket`](crate::packets::system::GetSystemVersionPacket).
+pub(crate) const SYSTEM_VERSION_REQUEST: [u8; 5] = [0xC9, 0x36, 0xB8, 0x47, 0xA4];
+
+/// The brain's reply to a system version request, reporting a major version of `major`.
+pub(crate) fn system_version_reply(major: u8) -> Vec<u8> {
+    vec![
+        0xAA, 0x55, 0xA4, 0x07, major, 0x02, 0x03, 0x04, 0x00, 0x10, 0x00,
+    ]
+}
+
+/// A CDC2 reply to the command with extended ID `ext_id`, for payloads under 128 bytes.
+pub(crate) fn cdc2_reply(ext_id: u8, ack: u8, payload: &[u8]) -> Vec<u8> {
+    let mut reply = vec![0xAA, 0x55, 0x56, payload.len() as u8 + 4, ext_id, ack];
+    reply.extend_from_slice(payload);
+    let crc = crate::crc::crc16(&reply);
+    reply.extend(crc.to_be_bytes());
+    reply
+}
+
+/// The brain's reply to a file read, carrying `data` from `address`, which has no ack byte.
+pub(crate) fn read_file_reply(address: u32, data: &[u8]) -> Vec<u8> {
+    let mut reply = vec![0xAA, 0x55, 0x56, data.len() as u8 + 7, 0x14];
+    reply.extend(address.to_le_bytes());
+    reply.extend(data);
+    let crc = crate::crc::crc16(&reply);
+    reply.extend(crc.to_be_bytes());
+    reply
+}
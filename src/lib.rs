@@ -7,11 +7,16 @@
 //! Because manually sending and receiving packets is a chore, this library also provides high level [`Command`](commands::Command)s.
 //! These commands provide easier ways to perform complicated tasks, such as uploading a program.
 
+// Lets the derive macros refer to `::vex_v5_serial` from within this crate.
+extern crate self as vex_v5_serial;
+
 pub mod array;
 pub mod choice;
 pub mod crc;
 pub mod decode;
 pub mod encode;
+#[cfg(test)]
+mod fixtures;
 pub mod packets;
 pub mod string;
 pub mod timestamp;
@@ -1,4 +1,9 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 
-pub type ScreenCapturePacket = Cdc2CommandPacket<86, 40, ()>;
-pub type ScreenCaptureReplyPacket = Cdc2ReplyPacket<86, 40, ()>;
+pub type ScreenCapturePacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::ScreenCapture.id() }, ()>;
+pub type ScreenCaptureReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::ScreenCapture.id() }, ()>;
@@ -1,13 +1,23 @@
 use std::fmt::Debug;
 
 use crate::{
-    decode::{Decode, DecodeError},
+    decode::{Counted, Decode, DecodeError},
     encode::{Encode, EncodeError},
-    varint::VarU16,
+    varint::{payload_size, VarU16},
 };
 
 use super::{DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
 
+wire_ids! {
+    /// The IDs of CDC commands, which come right after a packet's header.
+    pub enum CdcCommandId {
+        Query1 = 0x21,
+        /// Every CDC2 command shares this ID, and is told apart by its [extended ID](super::cdc2::Cdc2CommandId).
+        Cdc2 = 0x56,
+        GetSystemVersion = 0xA4,
+    }
+}
+
 /// CDC (Simple) Command Packet
 ///
 /// Encodes a simple device-bound message over the protocol containing
@@ -25,25 +35,44 @@ impl<const ID: u8, P: Encode> CdcCommandPacket<ID, P> {
             payload,
         }
     }
+
+    /// Creates a packet with a header other than the one devices expect.
+    ///
+    /// This is only useful for testing how malformed packets are handled.
+    #[cfg(any(test, feature = "fuzz"))]
+    pub fn with_header(header: [u8; 4], payload: P) -> Self {
+        Self { header, payload }
+    }
+
+    /// The packet's header, which is [`DEVICE_BOUND_HEADER`] unless it was built with `with_header`.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The payload the packet carries.
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
 }
 
 impl<const ID: u8, P: Encode> Encode for CdcCommandPacket<ID, P> {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        let mut encoded = Vec::new();
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
         // Push the header and ID
-        encoded.extend_from_slice(&self.header);
-        encoded.push(ID);
+        buf.extend_from_slice(&self.header);
+        buf.push(ID);
 
-        let payload_bytes = self.payload.encode()?;
+        // Write the payload in place, then slot its size in front of it.
+        let payload_start = buf.len();
+        self.payload.encode_into(buf)?;
+        let payload_len = buf.len() - payload_start;
 
         // We only encode the payload size if there is a payload
-        if !payload_bytes.is_empty() {
-            let size = VarU16::new(payload_bytes.len() as _);
-            encoded.extend(size.encode()?);
-            encoded.extend(payload_bytes);
+        if payload_len != 0 {
+            let size = payload_size(payload_len)?.encode()?;
+            buf.splice(payload_start..payload_start, size);
         }
 
-        Ok(encoded)
+        Ok(())
     }
 }
 
@@ -74,19 +103,45 @@ pub struct CdcReplyPacket<const ID: u8, P: Decode> {
     pub payload: P,
 }
 
+impl<const ID: u8, P: Decode> CdcReplyPacket<ID, P> {
+    /// The packet's header, which is always [`HOST_BOUND_HEADER`] once decoded.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The payload the packet carries.
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+}
+
 impl<const ID: u8, P: Decode> Decode for CdcReplyPacket<ID, P> {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-        let mut data = data.into_iter();
+        let mut data = Counted::new(data.into_iter());
         let header = Decode::decode(&mut data)?;
         if header != HOST_BOUND_HEADER {
             return Err(DecodeError::InvalidHeader);
         }
         let id = u8::decode(&mut data)?;
         if id != ID {
-            return Err(DecodeError::InvalidHeader);
+            return Err(DecodeError::UnexpectedCommandId {
+                expected: ID,
+                found: id,
+            });
         }
         let payload_size = VarU16::decode(&mut data)?;
-        let payload = P::decode(data)?;
+
+        // Only hand the payload its own bytes, so it can't read into whatever follows.
+        let payload_len = payload_size.into_inner() as usize;
+        let payload_start = data.position();
+        let payload_bytes = data.take(payload_len).collect::<Vec<_>>();
+        if payload_bytes.len() < payload_len {
+            return Err(DecodeError::UnexpectedEof {
+                needed: payload_len,
+                available: payload_bytes.len(),
+            });
+        }
+        let payload = P::decode(payload_bytes).map_err(|e| e.context("payload", payload_start))?;
 
         Ok(Self {
             header,
@@ -98,7 +153,7 @@ impl<const ID: u8, P: Decode> Decode for CdcReplyPacket<ID, P> {
 
 impl<const ID: u8, P: Decode + Debug> Debug for CdcReplyPacket<ID, P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("HostBoundPacket")
+        f.debug_struct("CdcReplyPacket")
             .field("header", &self.header)
             .field("payload_size", &self.payload_size)
             .field("payload", &self.payload)
@@ -115,3 +170,59 @@ impl<const ID: u8, P: Decode + Clone> Clone for CdcReplyPacket<ID, P> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CdcCommandPacket, CdcReplyPacket};
+    use crate::{
+        decode::{Decode, DecodeError},
+        encode::Encode,
+        fixtures::SYSTEM_VERSION_REQUEST,
+    };
+
+    #[test]
+    fn encode_empty() {
+        let packet = CdcCommandPacket::<0xA4, ()>::new(());
+
+        // Empty payloads don't get a size
+        assert_eq!(packet.encode().unwrap(), SYSTEM_VERSION_REQUEST);
+    }
+
+    #[test]
+    fn encode_payload() {
+        let packet = CdcCommandPacket::<0x21, Vec<u8>>::new(vec![0x01, 0x02, 0x03]);
+
+        assert_eq!(
+            packet.encode().unwrap(),
+            vec![0xC9, 0x36, 0xB8, 0x47, 0x21, 0x03, 0x01, 0x02, 0x03]
+        );
+    }
+
+    #[test]
+    fn decode_reply() {
+        // The trailing byte belongs to the next packet.
+        let reply =
+            CdcReplyPacket::<0x21, [u8; 2]>::decode(vec![0xAA, 0x55, 0x21, 0x02, 0x01, 0x02, 0xAA])
+                .unwrap();
+        assert_eq!(reply.payload, [0x01, 0x02]);
+
+        assert!(matches!(
+            CdcReplyPacket::<0x21, [u8; 2]>::decode(vec![0xAA, 0x55, 0x21, 0x03, 0x01, 0x02]),
+            Err(DecodeError::UnexpectedEof {
+                needed: 3,
+                available: 2
+            })
+        ));
+        assert!(matches!(
+            CdcReplyPacket::<0x21, [u8; 2]>::decode(vec![0xC9, 0x36, 0x21, 0x00]),
+            Err(DecodeError::InvalidHeader)
+        ));
+        assert!(matches!(
+            CdcReplyPacket::<0x21, [u8; 2]>::decode(vec![0xAA, 0x55, 0x22, 0x02, 0x01, 0x02]),
+            Err(DecodeError::UnexpectedCommandId {
+                expected: 0x21,
+                found: 0x22
+            })
+        ));
+    }
+}
@@ -1,21 +1,60 @@
 use thiserror::Error;
 
 use crate::{
-    crc::VEX_CRC16,
+    crc::crc16,
     decode::SizedDecode,
     encode::{Encode, EncodeError},
-    varint::VarU16,
+    varint::{payload_size, VarU16},
 };
 
 use super::{DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
 use crate::decode::{Decode, DecodeError};
 
+wire_ids! {
+    /// The extended IDs of CDC2 commands, which follow [`CdcCommandId::Cdc2`](super::cdc::CdcCommandId::Cdc2).
+    pub enum Cdc2CommandId {
+        SelectRadioChannel = 16,
+        InitFileTransfer = 17,
+        ExitFileTransfer = 18,
+        WriteFile = 19,
+        ReadFile = 20,
+        LinkFile = 21,
+        GetDirectoryFileCount = 22,
+        GetDirectoryEntry = 23,
+        LoadFileAction = 24,
+        GetFileMetadata = 25,
+        SetFileMetadata = 26,
+        EraseFile = 27,
+        GetProgramInfo = 28,
+        FileCleanUp = 30,
+        FileFormat = 31,
+        GetSystemFlags = 32,
+        GetDeviceStatus = 33,
+        GetSystemStatus = 34,
+        GetFdtStatus = 35,
+        GetLogCount = 36,
+        ReadLogPage = 37,
+        GetRadioStatus = 38,
+        UserFifo = 39,
+        ScreenCapture = 40,
+        SendDashTouch = 42,
+        SelectDash = 43,
+        ReadKeyValue = 46,
+        WriteKeyValue = 47,
+        GetSlot1To4Info = 49,
+        GetSlot5To8Info = 50,
+        CompetitionControl = 193,
+        GetFactoryStatus = 241,
+        FactoryEnable = 255,
+    }
+}
+
 /// CDC2 Packet Acknowledgement Codes
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Error)]
 pub enum Cdc2Ack {
     /// Acknowledges that a packet has been received successfully.
-    #[error("Packet was recieved successfully. Wait, how'd this happen??")]
+    #[error("Packet was received successfully. Wait, how'd this happen??")]
     Ack = 0x76,
 
     /// A general negative-acknowledgement (NACK) that is sometimes received.
@@ -53,7 +92,7 @@ pub enum Cdc2Ack {
     NackInvalidInitialization = 0xD5,
 
     /// Returned by the brain when we fail to pad a transfer to a four byte boundary.
-    #[error("File transfer was initialized incorrectly. (NACK 0xD6)")]
+    #[error("File transfer was not padded to a four byte boundary. (NACK 0xD6)")]
     NackAlignment = 0xD6,
 
     /// Returned by the brain when the addr on a file transfer does not match
@@ -110,12 +149,9 @@ impl Decode for Cdc2Ack {
             0xDC => Ok(Self::NackFileStorageFull),
             0x00 => Ok(Self::Timeout),
             0x01 => Ok(Self::WriteError),
-            v => Err(DecodeError::UnexpectedValue {
+            v => Err(DecodeError::UnknownDiscriminant {
+                type_name: "Cdc2Ack",
                 value: v,
-                expected: &[
-                    0x76, 0xFF, 0xCE, 0xD0, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9,
-                    0xDA, 0xDB, 0xDC, 0x00, 0x01,
-                ],
             }),
         }
     }
@@ -124,7 +160,6 @@ impl Decode for Cdc2Ack {
 pub struct Cdc2CommandPacket<const ID: u8, const EXT_ID: u8, P: Encode> {
     header: [u8; 4],
     payload: P,
-    crc: crc::Crc<u16>,
 }
 
 impl<P: Encode, const ID: u8, const EXTENDED_ID: u8> Cdc2CommandPacket<ID, EXTENDED_ID, P> {
@@ -133,43 +168,60 @@ impl<P: Encode, const ID: u8, const EXTENDED_ID: u8> Cdc2CommandPacket<ID, EXTEN
         Self {
             header: DEVICE_BOUND_HEADER,
             payload,
-            crc: VEX_CRC16,
         }
     }
+
+    /// Creates a packet with a header other than the one devices expect.
+    ///
+    /// This is only useful for testing how malformed packets are handled.
+    #[cfg(any(test, feature = "fuzz"))]
+    pub fn with_header(header: [u8; 4], payload: P) -> Self {
+        Self { header, payload }
+    }
+
+    /// The packet's header, which is [`DEVICE_BOUND_HEADER`] unless it was built with `with_header`.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The payload the packet carries.
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
 }
 
 impl<const ID: u8, const EXT_ID: u8, P: Encode> Encode for Cdc2CommandPacket<ID, EXT_ID, P> {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        let mut encoded = Vec::new();
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let packet_start = buf.len();
 
-        encoded.extend_from_slice(&self.header);
+        buf.extend_from_slice(&self.header);
 
         // Push IDs
-        encoded.push(ID);
-        encoded.push(EXT_ID);
+        buf.push(ID);
+        buf.push(EXT_ID);
 
-        // Push the payload size and encoded bytes
-        let payload_bytes = self.payload.encode()?;
-        let payload_size = VarU16::new(payload_bytes.len() as u16);
-        encoded.extend(payload_size.encode()?);
-        encoded.extend(payload_bytes);
+        // Write the payload in place, then slot its size in front of it.
+        let payload_start = buf.len();
+        self.payload.encode_into(buf)?;
+        let payload_len = buf.len() - payload_start;
+        let payload_size = payload_size(payload_len)?.encode()?;
+        buf.splice(payload_start..payload_start, payload_size);
 
         // The CRC32 checksum is of the whole encoded packet, meaning we need
         // to also include the header bytes.
-        let checksum = self.crc.checksum(&encoded);
+        let checksum = crc16(&buf[packet_start..]);
 
-        encoded.extend(checksum.to_be_bytes());
+        buf.extend(checksum.to_be_bytes());
 
-        Ok(encoded)
+        Ok(())
     }
 }
 
 impl<const ID: u8, const EXT_ID: u8, P: Encode + Clone> Clone for Cdc2CommandPacket<ID, EXT_ID, P> {
     fn clone(&self) -> Self {
         Self {
-            header: DEVICE_BOUND_HEADER,
+            header: self.header,
             payload: self.payload.clone(),
-            crc: self.crc.clone(),
         }
     }
 }
@@ -183,6 +235,16 @@ pub struct Cdc2ReplyPacket<const ID: u8, const EXT_ID: u8, P: SizedDecode> {
 }
 
 impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Cdc2ReplyPacket<ID, EXT_ID, P> {
+    /// The packet's header, which is always [`HOST_BOUND_HEADER`] once decoded.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The payload the packet carries.
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+
     pub fn try_into_inner(self) -> Result<P, Cdc2Ack> {
         if let Cdc2Ack::Ack = self.ack {
             Ok(self.payload)
@@ -194,7 +256,9 @@ impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Cdc2ReplyPacket<ID, EXT_ID,
 
 impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Decode for Cdc2ReplyPacket<ID, EXT_ID, P> {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-        let mut data = data.into_iter();
+        // Keep hold of the raw bytes so the CRC can be checked once we know where it is.
+        let bytes = take_reply(data.into_iter());
+        let mut data = bytes.iter().copied();
         let header = Decode::decode(&mut data)?;
         if header != HOST_BOUND_HEADER {
             return Err(DecodeError::InvalidHeader);
@@ -202,19 +266,49 @@ impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Decode for Cdc2ReplyPacket<
 
         let id = u8::decode(&mut data)?;
         if id != ID {
-            return Err(DecodeError::InvalidHeader);
+            return Err(DecodeError::UnexpectedCommandId {
+                expected: ID,
+                found: id,
+            });
         }
 
         let payload_size = VarU16::decode(&mut data)?;
 
         let ext_id = u8::decode(&mut data)?;
         if ext_id != EXT_ID {
-            return Err(DecodeError::InvalidHeader);
+            return Err(DecodeError::UnexpectedCommandId {
+                expected: EXT_ID,
+                found: ext_id,
+            });
         }
 
         let ack = Cdc2Ack::decode(&mut data)?;
-        let payload = P::sized_decode(&mut data, payload_size.into_inner())?;
-        let crc = u16::decode(&mut data)?;
+
+        // The size counts the extended ID, the ack and the CRC along with the payload.
+        // Only hand the payload its own bytes so that a payload which fails to decode
+        // (such as an `Option` in a NACK) can't eat into the CRC.
+        let payload_len = (payload_size.into_inner() as usize).saturating_sub(4);
+        let payload_start = bytes.len() - data.len();
+        let payload_bytes = (&mut data).take(payload_len).collect::<Vec<_>>();
+        if payload_bytes.len() < payload_len {
+            return Err(DecodeError::UnexpectedEof {
+                needed: payload_len,
+                available: payload_bytes.len(),
+            });
+        }
+        let payload = P::sized_decode(payload_bytes, payload_size.into_inner())
+            .map_err(|e| e.context("payload", payload_start))?;
+
+        // Like the command packet, the CRC16 covers everything before it and is big endian.
+        let checked_len = bytes.len() - data.len();
+        let crc = u16::from_be_bytes(Decode::decode(&mut data)?);
+        let expected = crc16(&bytes[..checked_len]);
+        if crc != expected {
+            return Err(DecodeError::BadCrc {
+                expected,
+                found: crc,
+            });
+        }
 
         Ok(Self {
             header,
@@ -226,6 +320,20 @@ impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Decode for Cdc2ReplyPacket<
     }
 }
 
+/// Takes a single reply's bytes: its header, ID and size, then as many bytes as the size says.
+///
+/// Anything past the end of the reply is left in `data`.
+pub(crate) fn take_reply(mut data: impl Iterator<Item = u8>) -> Vec<u8> {
+    let mut bytes = (&mut data).take(4).collect::<Vec<_>>();
+    if bytes.len() == 4 && VarU16::check_wide(bytes[3]) {
+        bytes.extend(data.next());
+    }
+    if let Ok(size) = VarU16::decode(bytes.get(3..).unwrap_or_default().iter().copied()) {
+        bytes.extend(data.take(size.into_inner() as usize));
+    }
+    bytes
+}
+
 impl<const ID: u8, const EXT_ID: u8, P: Decode + Clone> Clone for Cdc2ReplyPacket<ID, EXT_ID, P> {
     fn clone(&self) -> Self {
         Self {
@@ -237,3 +345,148 @@ impl<const ID: u8, const EXT_ID: u8, P: Decode + Clone> Clone for Cdc2ReplyPacke
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Cdc2Ack, Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket};
+    use crate::{
+        crc::crc16,
+        decode::{Decode, DecodeError},
+        encode::{Encode, EncodeError},
+    };
+
+    #[test]
+    fn encode_empty() {
+        let packet = Cdc2CommandPacket::<0x56, 0x20, ()>::new(());
+
+        // Extended packets always have a size, followed by a big endian CRC16
+        assert_eq!(
+            packet.encode().unwrap(),
+            vec![0xC9, 0x36, 0xB8, 0x47, 0x56, 0x20, 0x00, 0x06, 0x9E]
+        );
+    }
+
+    #[test]
+    fn encode_custom_header() {
+        let packet = Cdc2CommandPacket::<0x56, 0x20, ()>::with_header([0xAA, 0x55, 0x00, 0x00], ());
+        assert_eq!(packet.clone().header(), [0xAA, 0x55, 0x00, 0x00]);
+        let encoded = packet.encode().unwrap();
+
+        assert_eq!(encoded[..4], [0xAA, 0x55, 0x00, 0x00]);
+        // The CRC covers the header too
+        assert_eq!(&encoded[7..], &crc16(&encoded[..7]).to_be_bytes());
+    }
+
+    #[test]
+    fn encode_payload() {
+        let packet = Cdc2CommandPacket::<0x56, 0x2B, Vec<u8>>::new(vec![0x00, 0x00]);
+
+        assert_eq!(
+            packet.encode().unwrap(),
+            vec![0xC9, 0x36, 0xB8, 0x47, 0x56, 0x2B, 0x02, 0x00, 0x00, 0xB8, 0xAE]
+        );
+    }
+
+    #[test]
+    fn decode_nack_without_payload() {
+        let reply = Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(vec![
+            0xAA, 0x55, 0x56, 0x04, 0x19, 0xD3, 0x9D, 0xD8,
+        ])
+        .unwrap();
+
+        assert!(matches!(reply.ack, Cdc2Ack::NackProgramFile));
+        assert!(reply.payload.is_none());
+        // The failed payload must not have consumed the CRC.
+        assert_eq!(reply.crc, 0x9DD8);
+    }
+
+    #[test]
+    fn decode_payload() {
+        let reply = Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(vec![
+            0xAA, 0x55, 0x56, 0x08, 0x19, 0x76, 0x01, 0x00, 0x00, 0x00, 0x1E, 0xC0,
+        ])
+        .unwrap();
+
+        assert_eq!(reply.try_into_inner().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn decode_corrupted() {
+        // The payload's first byte was flipped from 0x01 in transit.
+        let result = Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(vec![
+            0xAA, 0x55, 0x56, 0x08, 0x19, 0x76, 0x02, 0x00, 0x00, 0x00, 0x1E, 0xC0,
+        ]);
+
+        assert!(matches!(
+            result,
+            Err(DecodeError::BadCrc { found: 0x1EC0, .. })
+        ));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let packet = [
+            0xAA, 0x55, 0x56, 0x08, 0x19, 0x76, 0x01, 0x00, 0x00, 0x00, 0x1E, 0xC0,
+        ];
+        for len in 0..packet.len() {
+            assert!(
+                Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(packet[..len].to_vec()).is_err()
+            );
+        }
+
+        assert!(matches!(
+            Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(packet[..8].to_vec()),
+            Err(DecodeError::UnexpectedEof {
+                needed: 4,
+                available: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_leaves_trailing_bytes() {
+        let mut data = [
+            0xAA, 0x55, 0x56, 0x08, 0x19, 0x76, 0x01, 0x00, 0x00, 0x00, 0x1E, 0xC0, 0xAA,
+        ]
+        .into_iter();
+
+        Cdc2ReplyPacket::<0x56, 0x19, Option<u32>>::decode(&mut data).unwrap();
+        assert_eq!(data.collect::<Vec<_>>(), [0xAA]);
+    }
+
+    #[test]
+    fn command_ids() {
+        assert_eq!(Cdc2CommandId::from(0x28), Cdc2CommandId::ScreenCapture);
+        assert_eq!(Cdc2CommandId::ScreenCapture.id(), 0x28);
+        assert_eq!(Cdc2CommandId::from(0x29), Cdc2CommandId::Unknown(0x29));
+        assert_eq!(u8::from(Cdc2CommandId::Unknown(0x29)), 0x29);
+    }
+
+    #[test]
+    fn unknown_ack() {
+        assert!(matches!(
+            Cdc2Ack::decode([0x42]),
+            Err(DecodeError::UnknownDiscriminant {
+                type_name: "Cdc2Ack",
+                value: 0x42
+            })
+        ));
+    }
+
+    #[test]
+    fn largest_payload() {
+        let encoded = Cdc2CommandPacket::<0x56, 0x13, _>::new(vec![0; 0x7FFF])
+            .encode()
+            .unwrap();
+        assert_eq!(encoded[6..8], [0xFF, 0xFF]);
+        assert_eq!(encoded.len(), 8 + 0x7FFF + 2);
+
+        assert!(matches!(
+            Cdc2CommandPacket::<0x56, 0x13, _>::new(vec![0; 0x8000]).encode(),
+            Err(EncodeError::PayloadTooLarge {
+                len: 0x8000,
+                max: 0x7FFF
+            })
+        ));
+    }
+}
@@ -1,12 +1,24 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+    system::CompetitionMode,
+};
 use crate::{
     decode::{Decode, DecodeError, SizedDecode},
     encode::{Encode, EncodeError},
     string::{DynamicVarLengthString, VarLengthString},
 };
 
-pub type UserFifoPacket = Cdc2CommandPacket<86, 39, UserFifoPayload>;
-pub type UserFifoReplyPacket = Cdc2ReplyPacket<86, 39, UserFifoReplyPayload>;
+pub type UserFifoPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::UserFifo.id() },
+    UserFifoPayload,
+>;
+pub type UserFifoReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::UserFifo.id() },
+    UserFifoReplyPayload,
+>;
 
 #[derive(Debug, Clone)]
 pub struct UserFifoPayload {
@@ -46,12 +58,18 @@ impl SizedDecode for UserFifoReplyPayload {
     ) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
         let channel = u8::decode(&mut data)?;
-        let data_len = payload_size - 5;
+        // The size also counts the extended ID, the ack, the channel and the CRC.
+        let data_len = payload_size
+            .checked_sub(5)
+            .ok_or(DecodeError::UnexpectedEof {
+                needed: 5,
+                available: payload_size as usize,
+            })?;
 
         let read = if data_len > 0 {
             Some(DynamicVarLengthString::decode_with_max_size(
                 &mut data,
-                (payload_size - 5) as usize,
+                data_len as usize,
             )?)
         } else {
             None
@@ -63,3 +81,63 @@ impl SizedDecode for UserFifoReplyPayload {
         })
     }
 }
+
+pub type CompetitionControlPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::CompetitionControl.id() },
+    CompetitionControlPayload,
+>;
+pub type CompetitionControlReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::CompetitionControl.id() }, ()>;
+
+/// Sets the competition mode of the brain a controller is paired with, as a field controller would.
+#[derive(Debug, Clone, Copy)]
+pub struct CompetitionControlPayload {
+    pub mode: CompetitionMode,
+    /// Time elapsed in the match. (RESEARCH NEEDED)
+    pub match_time: u32,
+}
+impl Encode for CompetitionControlPayload {
+    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        let mode: u8 = match self.mode {
+            CompetitionMode::Driver => 0b1000,
+            CompetitionMode::Autonomous => 0b1010,
+            CompetitionMode::Disabled => 0b1011,
+        };
+
+        let mut encoded = vec![mode];
+        encoded.extend(self.match_time.to_le_bytes());
+        Ok(encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompetitionControlPayload, UserFifoReplyPayload};
+    use crate::decode::{DecodeError, SizedDecode};
+    use crate::encode::Encode;
+    use crate::packets::system::CompetitionMode;
+
+    #[test]
+    fn size_too_small() {
+        let result = UserFifoReplyPayload::sized_decode([0x01], 3);
+
+        assert!(matches!(
+            result,
+            Err(DecodeError::UnexpectedEof {
+                needed: 5,
+                available: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn competition_control() {
+        let payload = CompetitionControlPayload {
+            mode: CompetitionMode::Autonomous,
+            match_time: 0,
+        };
+
+        assert_eq!(payload.encode().unwrap(), [0x0A, 0x00, 0x00, 0x00, 0x00]);
+    }
+}
@@ -1,8 +1,15 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
-use crate::encode::{Encode, EncodeError};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
+use crate::{
+    decode::DecodeError,
+    encode::{Encode, EncodeError},
+};
 
+/// A screen of the brain's built-in dashboard.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DashScreen {
     Home = 0,
     Battery = 1,
@@ -42,8 +49,63 @@ pub enum DashScreen {
     LogData = 47,
 }
 
-pub type SendDashTouchPacket = Cdc2CommandPacket<86, 42, SendDashTouchPayload>;
-pub type SendDashTouchReplyPacket = Cdc2ReplyPacket<86, 42, ()>;
+impl TryFrom<u8> for DashScreen {
+    type Error = DecodeError;
+
+    /// Checks that a raw screen ID is one the brain knows about.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Home),
+            1 => Ok(Self::Battery),
+            3 => Ok(Self::Led),
+            4 => Ok(Self::MatchConfig),
+            5 => Ok(Self::MatchConfigMore),
+            6 => Ok(Self::Wiring),
+            8 => Ok(Self::Radio),
+            10 => Ok(Self::Brain),
+            13 => Ok(Self::RunProgram),
+            14 => Ok(Self::DriveProgramControlLeftMapping),
+            15 => Ok(Self::DriveProgramMenu),
+            16 => Ok(Self::Devices),
+            17 => Ok(Self::UserProgramFolder),
+            18 => Ok(Self::VexProgramFolder),
+            19 => Ok(Self::Settings),
+            20 => Ok(Self::ScaryConfiguration),
+            21 => Ok(Self::Language),
+            22 => Ok(Self::DriveMotorConfig),
+            24 => Ok(Self::ProgramMenu),
+            25 => Ok(Self::Shutdown),
+            26 => Ok(Self::Controller2Mapping),
+            27 => Ok(Self::ScaryConfigurationMore),
+            28 => Ok(Self::ConfirmXX),
+            29 => Ok(Self::Controller1Mapping),
+            30 => Ok(Self::DriveProgramControlDualMapping),
+            31 => Ok(Self::DriveProgramControlSplitMapping),
+            32 => Ok(Self::DriveProgramControlRightMapping),
+            33 => Ok(Self::Match24Players),
+            34 => Ok(Self::EventLog),
+            40 => Ok(Self::UserProgramWiring),
+            41 => Ok(Self::ClawbotProgramMenu),
+            42 => Ok(Self::About),
+            43 => Ok(Self::LanguageMore),
+            45 => Ok(Self::ObjectColor),
+            46 => Ok(Self::SignatureId),
+            47 => Ok(Self::LogData),
+            value => Err(DecodeError::UnknownDiscriminant {
+                type_name: "DashScreen",
+                value,
+            }),
+        }
+    }
+}
+
+pub type SendDashTouchPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::SendDashTouch.id() },
+    SendDashTouchPayload,
+>;
+pub type SendDashTouchReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::SendDashTouch.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct SendDashTouchPayload {
@@ -62,8 +124,13 @@ impl Encode for SendDashTouchPayload {
     }
 }
 
-pub type SelectDashPacket = Cdc2CommandPacket<86, 43, SelectDashPayload>;
-pub type SelectDashReplyPacket = Cdc2ReplyPacket<86, 43, ()>;
+pub type SelectDashPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::SelectDash.id() },
+    SelectDashPayload,
+>;
+pub type SelectDashReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::SelectDash.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct SelectDashPayload {
@@ -76,3 +143,22 @@ impl Encode for SelectDashPayload {
         Ok(vec![self.screen as u8, self.port])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::DashScreen;
+    use crate::decode::DecodeError;
+
+    #[test]
+    fn screen_ids() {
+        assert_eq!(DashScreen::try_from(19).unwrap(), DashScreen::Settings);
+        assert_eq!(DashScreen::try_from(47).unwrap() as u8, 47);
+        assert!(matches!(
+            DashScreen::try_from(2),
+            Err(DecodeError::UnknownDiscriminant {
+                type_name: "DashScreen",
+                value: 2
+            })
+        ));
+    }
+}
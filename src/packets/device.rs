@@ -1,86 +1,50 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 use crate::{
     array::Array,
     decode::{Decode, DecodeError},
 };
 
 // This is copied from vex-sdk
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[repr(u8)]
-pub enum DeviceType {
-    NoSensor = 0,
-    Motor = 2,
-    Led = 3,
-    AbsEncoder = 4,
-    CrMotor = 5,
-    Imu = 6,
-    DistanceSensor = 7,
-    Radio = 8,
-    TetheredController = 9,
-    Brain = 10,
-    VisionSensor = 11,
-    AdiExpander = 12,
-    Res1Sensor = 13,
-    Battery = 14,
-    Res3Sensor = 15,
-    OpticalSensor = 16,
-    Magnet = 17,
-    GpsSensor = 20,
-    AicameraSensor = 26,
-    LightTower = 27,
-    ArmDevice = 28,
-    AiVisionSensor = 29,
-    Pneumatic = 30,
-    BumperSensor = 0x40,
-    GyroSensor = 0x46,
-    SonarSensor = 0x47,
-    GenericSensor = 128,
-    GenericSerial = 129,
-    UndefinedSensor = 255,
+wire_ids! {
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum DeviceType {
+        NoSensor = 0,
+        Motor = 2,
+        Led = 3,
+        AbsEncoder = 4,
+        CrMotor = 5,
+        Imu = 6,
+        DistanceSensor = 7,
+        Radio = 8,
+        TetheredController = 9,
+        Brain = 10,
+        VisionSensor = 11,
+        AdiExpander = 12,
+        Res1Sensor = 13,
+        Battery = 14,
+        Res3Sensor = 15,
+        OpticalSensor = 16,
+        Magnet = 17,
+        GpsSensor = 20,
+        AicameraSensor = 26,
+        LightTower = 27,
+        ArmDevice = 28,
+        AiVisionSensor = 29,
+        Pneumatic = 30,
+        BumperSensor = 0x40,
+        GyroSensor = 0x46,
+        SonarSensor = 0x47,
+        GenericSensor = 128,
+        GenericSerial = 129,
+        UndefinedSensor = 255,
+    }
 }
 impl Decode for DeviceType {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-        let value = u8::decode(data)?;
-        Ok(match value {
-            0 => DeviceType::NoSensor,
-            2 => DeviceType::Motor,
-            3 => DeviceType::Led,
-            4 => DeviceType::AbsEncoder,
-            5 => DeviceType::CrMotor,
-            6 => DeviceType::Imu,
-            7 => DeviceType::DistanceSensor,
-            8 => DeviceType::Radio,
-            9 => DeviceType::TetheredController,
-            10 => DeviceType::Brain,
-            11 => DeviceType::VisionSensor,
-            12 => DeviceType::AdiExpander,
-            13 => DeviceType::Res1Sensor,
-            14 => DeviceType::Battery,
-            15 => DeviceType::Res3Sensor,
-            16 => DeviceType::OpticalSensor,
-            17 => DeviceType::Magnet,
-            20 => DeviceType::GpsSensor,
-            26 => DeviceType::AicameraSensor,
-            27 => DeviceType::LightTower,
-            28 => DeviceType::ArmDevice,
-            29 => DeviceType::AiVisionSensor,
-            30 => DeviceType::Pneumatic,
-            0x40 => DeviceType::BumperSensor,
-            0x46 => DeviceType::GyroSensor,
-            0x47 => DeviceType::SonarSensor,
-            128 => DeviceType::GenericSensor,
-            129 => DeviceType::GenericSerial,
-            255 => DeviceType::UndefinedSensor,
-            _ => {
-                return Err(DecodeError::UnexpectedValue {
-                    value,
-                    expected: &[
-                        0, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 20, 26, 27, 28,
-                        29, 30, 0x40, 0x46, 0x47, 128, 129, 255,
-                    ],
-                })
-            }
-        })
+        Ok(u8::decode(data)?.into())
     }
 }
 
@@ -117,8 +81,13 @@ impl Decode for DeviceStatus {
     }
 }
 
-pub type GetDeviceStatusPacket = Cdc2CommandPacket<86, 33, ()>;
-pub type GetDeviceStatusReplyPacket = Cdc2ReplyPacket<86, 33, GetDeviceStatusReplyPayload>;
+pub type GetDeviceStatusPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetDeviceStatus.id() }, ()>;
+pub type GetDeviceStatusReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetDeviceStatus.id() },
+    GetDeviceStatusReplyPayload,
+>;
 
 pub struct GetDeviceStatusReplyPayload {
     /// Number of elements in the following array.
@@ -133,3 +102,22 @@ impl Decode for GetDeviceStatusReplyPayload {
         Ok(Self { count, devices })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceType;
+    use crate::decode::Decode;
+
+    #[test]
+    fn device_type() {
+        assert_eq!(
+            DeviceType::decode([0x10]).unwrap(),
+            DeviceType::OpticalSensor
+        );
+        // Devices added by newer firmware shouldn't fail the whole status reply.
+        assert_eq!(
+            DeviceType::decode([0x20]).unwrap(),
+            DeviceType::Unknown(0x20)
+        );
+    }
+}
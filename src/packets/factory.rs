@@ -1,6 +1,9 @@
 //! Factory Control
 
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 use crate::{
     array::Array,
     decode::{Decode, DecodeError},
@@ -52,6 +55,7 @@ impl Decode for Fdt {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FactoryStatus {
     pub status: u8,
     pub percent: u8,
@@ -65,20 +69,32 @@ impl Decode for FactoryStatus {
     }
 }
 
-pub type GetFdtStatusPacket = Cdc2CommandPacket<86, 35, ()>;
-pub type GetFdtStatusReplyPacket = Cdc2ReplyPacket<86, 35, FdtStatus>;
+pub type GetFdtStatusPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetFdtStatus.id() }, ()>;
+pub type GetFdtStatusReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetFdtStatus.id() }, FdtStatus>;
 
-pub type GetFactoryStatusPacket = Cdc2CommandPacket<86, 241, ()>;
-pub type GetFactoryStatusReplyPacket = Cdc2ReplyPacket<86, 241, FactoryStatus>;
+pub type GetFactoryStatusPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetFactoryStatus.id() }, ()>;
+pub type GetFactoryStatusReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetFactoryStatus.id() },
+    FactoryStatus,
+>;
 
-pub type FactoryEnablePacket = Cdc2CommandPacket<86, 255, FactoryEnablePayload>;
-pub type FactoryEnableReplyPacket = Cdc2CommandPacket<86, 255, ()>;
+pub type FactoryEnablePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::FactoryEnable.id() },
+    FactoryEnablePayload,
+>;
+pub type FactoryEnableReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::FactoryEnable.id() }, ()>;
 
 #[derive(Debug, Clone, Copy)]
 pub struct FactoryEnablePayload(pub [u8; 4]);
 impl Encode for FactoryEnablePayload {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        Ok(self.0.to_vec())
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        self.0.encode_into(buf)
     }
 }
 
@@ -3,15 +3,18 @@
 use std::vec;
 
 use super::{
-    cdc::CdcReplyPacket,
-    cdc2::{Cdc2Ack, Cdc2CommandPacket, Cdc2ReplyPacket},
+    cdc::CdcCommandId,
+    cdc2::{take_reply, Cdc2Ack, Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+    HOST_BOUND_HEADER,
 };
 use crate::{
     array::Array,
     choice::{Choice, PrefferedChoice},
+    crc::crc16,
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
     string::FixedLengthString,
+    varint::VarU16,
     version::Version,
 };
 
@@ -75,11 +78,9 @@ impl Decode for FileVendor {
             64 => Ok(Self::VexVm),
             240 => Ok(Self::Vex),
             241 => Ok(Self::Undefined),
-            v => Err(DecodeError::UnexpectedValue {
+            v => Err(DecodeError::UnknownDiscriminant {
+                type_name: "FileVendor",
                 value: v,
-                expected: &[
-                    0x01, 0x0F, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0x40, 0xF0, 0xF1,
-                ],
             }),
         }
     }
@@ -93,8 +94,16 @@ pub enum FileLoadAction {
 }
 
 /// Start uploading or downloading file from the device
-pub type InitFileTransferPacket = Cdc2CommandPacket<86, 17, InitFileTransferPayload>;
-pub type InitFileTransferReplyPacket = Cdc2ReplyPacket<86, 17, InitFileTransferReplyPayload>;
+pub type InitFileTransferPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::InitFileTransfer.id() },
+    InitFileTransferPayload,
+>;
+pub type InitFileTransferReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::InitFileTransfer.id() },
+    InitFileTransferReplyPayload,
+>;
 
 #[derive(Debug, Clone)]
 pub struct InitFileTransferPayload {
@@ -162,8 +171,13 @@ impl Decode for InitFileTransferReplyPayload {
 }
 
 /// Finish uploading or downloading file from the device
-pub type ExitFileTransferPacket = Cdc2CommandPacket<86, 18, FileExitAction>;
-pub type ExitFileTransferReplyPacket = Cdc2ReplyPacket<86, 18, ()>;
+pub type ExitFileTransferPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ExitFileTransfer.id() },
+    FileExitAction,
+>;
+pub type ExitFileTransferReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::ExitFileTransfer.id() }, ()>;
 
 /// The action to run when a file transfer is completed.
 #[repr(u8)]
@@ -180,8 +194,13 @@ impl Encode for FileExitAction {
     }
 }
 /// Write to the brain
-pub type WriteFilePacket = Cdc2CommandPacket<86, 19, WriteFilePayload>;
-pub type WriteFileReplyPacket = Cdc2ReplyPacket<86, 19, ()>;
+pub type WriteFilePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::WriteFile.id() },
+    WriteFilePayload,
+>;
+pub type WriteFileReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::WriteFile.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct WriteFilePayload {
@@ -203,9 +222,82 @@ impl Encode for WriteFilePayload {
 }
 
 /// Read from the brain
-pub type ReadFilePacket = Cdc2CommandPacket<86, 20, ReadFilePayload>;
+pub type ReadFilePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ReadFile.id() },
+    ReadFilePayload,
+>;
 /// Returns the file content. This packet doesn't have an ack if the data is available.
-pub type ReadFileReplyPacket = CdcReplyPacket<86, ReadFileReplyPayload>;
+///
+/// Like other CDC2 replies, it ends in a CRC16 of the whole packet, which is checked when
+/// the packet is decoded.
+pub struct ReadFileReplyPacket {
+    pub header: [u8; 2],
+    pub payload_size: VarU16,
+    pub payload: ReadFileReplyPayload,
+}
+impl ReadFileReplyPacket {
+    /// The packet's header, which is always [`HOST_BOUND_HEADER`] once decoded.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The payload the packet carries.
+    pub fn payload(&self) -> &ReadFileReplyPayload {
+        &self.payload
+    }
+}
+impl Decode for ReadFileReplyPacket {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        let bytes = take_reply(data.into_iter());
+        let mut data = bytes.iter().copied();
+        let header = Decode::decode(&mut data)?;
+        if header != HOST_BOUND_HEADER {
+            return Err(DecodeError::InvalidHeader);
+        }
+
+        let id = u8::decode(&mut data)?;
+        if id != CdcCommandId::Cdc2.id() {
+            return Err(DecodeError::UnexpectedCommandId {
+                expected: CdcCommandId::Cdc2.id(),
+                found: id,
+            });
+        }
+
+        let payload_size = VarU16::decode(&mut data)?;
+        let payload_len = payload_size.into_inner() as usize;
+        let payload_start = bytes.len() - data.len();
+        // This checks the extended ID first, so replies to other commands are told apart
+        // even if they're cut short.
+        let payload = ReadFileReplyPayload::decode(&mut data)
+            .map_err(|e| e.context("payload", payload_start))?;
+        if bytes.len() < payload_start + payload_len {
+            return Err(DecodeError::UnexpectedEof {
+                needed: payload_len,
+                available: bytes.len() - payload_start,
+            });
+        }
+
+        // The CRC16 covers everything before it.
+        let crc = match payload.contents {
+            ReadFileReplyContents::Success { crc, .. }
+            | ReadFileReplyContents::Failure { crc, .. } => crc,
+        };
+        let expected = crc16(&bytes[..bytes.len() - 2]);
+        if crc != expected {
+            return Err(DecodeError::BadCrc {
+                expected,
+                found: crc,
+            });
+        }
+
+        Ok(Self {
+            header,
+            payload_size,
+            payload,
+        })
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ReadFilePayload {
@@ -251,7 +343,14 @@ impl Decode for ReadFileReplyContents {
                 // This is a cursed way to get the number of bytes in chunk_data.
                 let data_vec = data.collect::<Vec<_>>();
                 // The last two bytes are the CRC checksum.
-                let num_bytes = data_vec.len() - 2;
+                let num_bytes =
+                    data_vec
+                        .len()
+                        .checked_sub(2)
+                        .ok_or(DecodeError::UnexpectedEof {
+                            needed: 2,
+                            available: data_vec.len(),
+                        })?;
                 let mut data = data_vec.into_iter();
 
                 let chunk_data = Array::decode_with_len(&mut data, num_bytes)?;
@@ -301,10 +400,10 @@ impl Decode for ReadFileReplyPayload {
     {
         let mut data = data.into_iter();
         let id = u8::decode(&mut data)?;
-        if id != 0x14 {
-            return Err(DecodeError::UnexpectedValue {
-                value: id,
-                expected: &[0x14],
+        if id != Cdc2CommandId::ReadFile.id() {
+            return Err(DecodeError::UnexpectedCommandId {
+                expected: Cdc2CommandId::ReadFile.id(),
+                found: id,
             });
         }
         let contents = ReadFileReplyContents::decode(&mut data)?;
@@ -327,8 +426,13 @@ impl ReadFileReplyPayload {
 /// File linking means allowing one file to be loaded after another file first (its parent).
 ///
 /// This is used in PROS for the hot/cold linking.
-pub type LinkFilePacket = Cdc2CommandPacket<86, 21, LinkFilePayload>;
-pub type LinkFileReplyPacket = Cdc2ReplyPacket<86, 21, ()>;
+pub type LinkFilePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::LinkFile.id() },
+    LinkFilePayload,
+>;
+pub type LinkFileReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::LinkFile.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct LinkFilePayload {
@@ -347,8 +451,16 @@ impl Encode for LinkFilePayload {
     }
 }
 
-pub type GetDirectoryFileCountPacket = Cdc2CommandPacket<86, 22, GetDirectoryFileCountPayload>;
-pub type GetDirectoryFileCountReplyPacket = Cdc2ReplyPacket<86, 22, u16>;
+pub type GetDirectoryFileCountPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetDirectoryFileCount.id() },
+    GetDirectoryFileCountPayload,
+>;
+pub type GetDirectoryFileCountReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetDirectoryFileCount.id() },
+    u16,
+>;
 
 #[derive(Debug, Clone)]
 pub struct GetDirectoryFileCountPayload {
@@ -362,9 +474,16 @@ impl Encode for GetDirectoryFileCountPayload {
     }
 }
 
-pub type GetDirectoryEntryPacket = Cdc2CommandPacket<86, 23, GetDirectoryEntryPayload>;
-pub type GetDirectoryEntryReplyPacket =
-    Cdc2ReplyPacket<86, 23, Option<GetDirectoryEntryReplyPayload>>;
+pub type GetDirectoryEntryPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetDirectoryEntry.id() },
+    GetDirectoryEntryPayload,
+>;
+pub type GetDirectoryEntryReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetDirectoryEntry.id() },
+    Option<GetDirectoryEntryReplyPayload>,
+>;
 
 #[derive(Debug, Clone, Copy)]
 pub struct GetDirectoryEntryPayload {
@@ -420,8 +539,13 @@ impl Decode for GetDirectoryEntryReplyPayload {
 }
 
 /// Run a binrary file on the brain or stop the program running on the brain.
-pub type LoadFileActionPacket = Cdc2CommandPacket<86, 24, LoadFileActionPayload>;
-pub type LoadFileActionReplyPacket = Cdc2ReplyPacket<86, 24, ()>;
+pub type LoadFileActionPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::LoadFileAction.id() },
+    LoadFileActionPayload,
+>;
+pub type LoadFileActionReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::LoadFileAction.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct LoadFileActionPayload {
@@ -438,8 +562,16 @@ impl Encode for LoadFileActionPayload {
         Ok(encoded)
     }
 }
-pub type GetFileMetadataPacket = Cdc2CommandPacket<86, 25, GetFileMetadataPayload>;
-pub type GetFileMetadataReplyPacket = Cdc2ReplyPacket<86, 25, Option<GetFileMetadataReplyPayload>>;
+pub type GetFileMetadataPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetFileMetadata.id() },
+    GetFileMetadataPayload,
+>;
+pub type GetFileMetadataReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetFileMetadata.id() },
+    Option<GetFileMetadataReplyPayload>,
+>;
 
 #[derive(Debug, Clone)]
 pub struct GetFileMetadataPayload {
@@ -493,8 +625,13 @@ impl Decode for GetFileMetadataReplyPayload {
     }
 }
 
-pub type SetFileMetadataPacket = Cdc2CommandPacket<86, 26, SetFileMetadataPayload>;
-pub type SetFileMetadataReplyPacket = Cdc2ReplyPacket<86, 26, ()>;
+pub type SetFileMetadataPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::SetFileMetadata.id() },
+    SetFileMetadataPayload,
+>;
+pub type SetFileMetadataReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::SetFileMetadata.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct SetFileMetadataPayload {
@@ -520,8 +657,13 @@ impl Encode for SetFileMetadataPayload {
     }
 }
 
-pub type EraseFilePacket = Cdc2CommandPacket<86, 27, EraseFilePayload>;
-pub type EraseFileReplyPacket = Cdc2ReplyPacket<86, 27, ()>;
+pub type EraseFilePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::EraseFile.id() },
+    EraseFilePayload,
+>;
+pub type EraseFileReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::EraseFile.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct EraseFilePayload {
@@ -539,8 +681,16 @@ impl Encode for EraseFilePayload {
     }
 }
 
-pub type FileCleanUpPacket = Cdc2CommandPacket<86, 30, FileCleanUpPayload>;
-pub type FileCleanUpReplyPacket = Cdc2CommandPacket<86, 30, FileCleanUpResult>;
+pub type FileCleanUpPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::FileCleanUp.id() },
+    FileCleanUpPayload,
+>;
+pub type FileCleanUpReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::FileCleanUp.id() },
+    FileCleanUpResult,
+>;
 
 #[derive(Debug, Clone)]
 pub struct FileCleanUpPayload {
@@ -582,25 +732,80 @@ impl Decode for FileCleanUpResult {
             2 => Ok(Self::LinkedFiles),
             3 => Ok(Self::AllFilesAfterRestart),
             4 => Ok(Self::LinkedFilesAfterRestart),
-            value => Err(DecodeError::UnexpectedValue {
+            value => Err(DecodeError::UnknownDiscriminant {
+                type_name: "FileCleanUpResult",
                 value,
-                expected: &[0x00, 0x01, 0x02, 0x03, 0x04],
             }),
         }
     }
 }
 
 /// Same as "File Clear Up", but takes longer
-pub type FileFormatPacket = Cdc2CommandPacket<86, 31, FileFormatConfirmation>;
-pub type FileFormatReplyPacket = Cdc2CommandPacket<86, 31, ()>;
+pub type FileFormatPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::FileFormat.id() },
+    FileFormatConfirmation,
+>;
+pub type FileFormatReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::FileFormat.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct FileFormatConfirmation {
     /// Must be [0x44, 0x43, 0x42, 0x41].
     pub confirmation_code: [u8; 4],
 }
+impl Default for FileFormatConfirmation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl FileFormatConfirmation {
+    pub const FORMAT_BYTES: [u8; 4] = [0x44, 0x43, 0x42, 0x41];
+
+    pub const fn new() -> Self {
+        Self {
+            confirmation_code: Self::FORMAT_BYTES,
+        }
+    }
+}
 impl Encode for FileFormatConfirmation {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        Ok(self.confirmation_code.to_vec())
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        self.confirmation_code.encode_into(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadFileReplyPacket;
+    use crate::{
+        decode::{Decode, DecodeError},
+        fixtures::{cdc2_reply, read_file_reply},
+    };
+
+    #[test]
+    fn decode_read_reply() {
+        let reply =
+            ReadFileReplyPacket::decode(read_file_reply(0x0380_0000, &[1, 2, 3, 4])).unwrap();
+        let (address, data) = reply.payload.unwrap().unwrap();
+
+        assert_eq!(address, 0x0380_0000);
+        assert_eq!(data.into_inner(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn read_reply_crc() {
+        let mut reply = read_file_reply(0x0380_0000, &[1, 2, 3, 4]);
+        reply[9] ^= 0xFF;
+
+        let result = ReadFileReplyPacket::decode(reply);
+        assert!(matches!(result, Err(DecodeError::BadCrc { .. })));
+    }
+
+    #[test]
+    fn other_cdc2_reply() {
+        // A late reply to the InitFileTransfer that started the download.
+        let result = ReadFileReplyPacket::decode(cdc2_reply(0x11, 0x76, &[0; 10]));
+
+        assert!(result.is_err_and(|e| e.is_other_packet()));
     }
 }
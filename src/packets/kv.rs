@@ -1,17 +1,34 @@
 //! Global key-value store.
 
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 use crate::{
     encode::{Encode, EncodeError},
     string::{FixedLengthString, VarLengthString},
 };
 
-pub type ReadKeyValuePacket = Cdc2CommandPacket<86, 46, FixedLengthString<31>>;
-pub type ReadKeyValueReplyPacket = Cdc2ReplyPacket<86, 46, VarLengthString<255>>;
+pub type ReadKeyValuePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ReadKeyValue.id() },
+    FixedLengthString<31>,
+>;
+pub type ReadKeyValueReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ReadKeyValue.id() },
+    VarLengthString<255>,
+>;
 
-pub type WriteKeyValuePacket = Cdc2CommandPacket<86, 47, WriteKeyValuePayload>;
-pub type WriteKeyValueReplyPacket = Cdc2ReplyPacket<86, 47, ()>;
+pub type WriteKeyValuePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::WriteKeyValue.id() },
+    WriteKeyValuePayload,
+>;
+pub type WriteKeyValueReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::WriteKeyValue.id() }, ()>;
 
+#[derive(Debug, Clone)]
 pub struct WriteKeyValuePayload {
     pub key: VarLengthString<31>,
     pub value: VarLengthString<255>,
@@ -1,10 +1,16 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+use std::time::Duration;
+
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 use crate::{
     array::Array,
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
 };
 
+#[derive(Debug, Clone, Copy)]
 pub struct Log {
     /// (RESEARCH NEEDED)
     pub code: u8,
@@ -38,9 +44,22 @@ impl Decode for Log {
         })
     }
 }
+impl Log {
+    /// How long after the brain powered on this was logged.
+    ///
+    /// Log entries don't record the time of day.
+    pub fn time_since_boot(&self) -> Duration {
+        Duration::from_millis(self.time as u64)
+    }
+}
 
-pub type GetLogCountPacket = Cdc2CommandPacket<86, 36, ()>;
-pub type GetLogCountReplyPacket = Cdc2ReplyPacket<86, 36, GetLogCountReplyPayload>;
+pub type GetLogCountPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetLogCount.id() }, ()>;
+pub type GetLogCountReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetLogCount.id() },
+    GetLogCountReplyPayload,
+>;
 
 pub struct GetLogCountReplyPayload {
     pub unknown: u8,
@@ -56,8 +75,16 @@ impl Decode for GetLogCountReplyPayload {
 }
 
 /// For example: If the brain has 26 logs, from A to Z. With offset 5 and count 5, it returns [V, W, X, Y, Z]. With offset 10 and count 5, it returns [Q, R, S, T, U].
-pub type ReadLogPagePacket = Cdc2CommandPacket<86, 37, ReadLogPagePayload>;
-pub type ReadLogPageReplyPacket = Cdc2ReplyPacket<86, 37, ReadLogPageReplyPayload>;
+pub type ReadLogPagePacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ReadLogPage.id() },
+    ReadLogPagePayload,
+>;
+pub type ReadLogPageReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::ReadLogPage.id() },
+    ReadLogPageReplyPayload,
+>;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ReadLogPagePayload {
@@ -1,5 +1,45 @@
 use crate::decode::{Decode, DecodeError};
 
+/// Defines an enum of IDs sent over the wire, with an `Unknown` variant for any other ID.
+macro_rules! wire_ids {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            /// An ID this crate doesn't know about.
+            Unknown(u8),
+        }
+        impl $name {
+            /// The ID as sent over the wire.
+            pub const fn id(self) -> u8 {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(id) => id,
+                }
+            }
+        }
+        impl From<u8> for $name {
+            fn from(id: u8) -> Self {
+                match id {
+                    $($value => Self::$variant,)*
+                    id => Self::Unknown(id),
+                }
+            }
+        }
+        impl From<$name> for u8 {
+            fn from(id: $name) -> Self {
+                id.id()
+            }
+        }
+    };
+}
+
 pub mod capture;
 pub mod cdc;
 pub mod cdc2;
@@ -1,5 +1,8 @@
-use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
 use super::file::FileVendor;
+use super::{
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
+};
 use crate::array::Array;
 use crate::{
     decode::{Decode, DecodeError},
@@ -7,29 +10,147 @@ use crate::{
     string::{DynamicVarLengthString, FixedLengthString},
 };
 
+/// One of the icons shown next to a program on the brain's program menu.
+///
+/// Icons are stored on the brain as `USER???x.bmp`, where `???` is the icon's number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SlotIcon {
+    VexCodingStudio,
+    CoolX,
+    /// Shown when the icon file doesn't exist.
+    QuestionMark,
+    Pizza,
+    Clawbot,
+    Robot,
+    PowerButton,
+    Planets,
+    Alien,
+    AlienInUfo,
+    CupInField,
+    CupAndBall,
+    Matlab,
+    Pros,
+    RobotMesh,
+    RobotMeshCpp,
+    RobotMeshBlockly,
+    RobotMeshFlowol,
+    RobotMeshJs,
+    RobotMeshPy,
+    CodeFile,
+    VexcodeBrackets,
+    VexcodeBlocks,
+    VexcodePython,
+    VexcodeCpp,
+    /// An icon without a name here, such as a custom one.
+    Other(u16),
+}
+impl SlotIcon {
+    /// The name of the icon's file on the brain, as used in a program's ini file.
+    pub fn file_name(&self) -> String {
+        format!("USER{:03}x.bmp", u16::from(*self))
+    }
+}
+impl From<u16> for SlotIcon {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::VexCodingStudio,
+            1 => Self::CoolX,
+            2 => Self::QuestionMark,
+            3 => Self::Pizza,
+            10 => Self::Clawbot,
+            11 => Self::Robot,
+            12 => Self::PowerButton,
+            13 => Self::Planets,
+            27 => Self::Alien,
+            29 => Self::AlienInUfo,
+            50 => Self::CupInField,
+            51 => Self::CupAndBall,
+            901 => Self::Matlab,
+            902 => Self::Pros,
+            903 => Self::RobotMesh,
+            911 => Self::RobotMeshCpp,
+            912 => Self::RobotMeshBlockly,
+            913 => Self::RobotMeshFlowol,
+            914 => Self::RobotMeshJs,
+            915 => Self::RobotMeshPy,
+            920 => Self::CodeFile,
+            921 => Self::VexcodeBrackets,
+            922 => Self::VexcodeBlocks,
+            925 => Self::VexcodePython,
+            926 => Self::VexcodeCpp,
+            v => Self::Other(v),
+        }
+    }
+}
+impl From<SlotIcon> for u16 {
+    fn from(value: SlotIcon) -> Self {
+        match value {
+            SlotIcon::VexCodingStudio => 0,
+            SlotIcon::CoolX => 1,
+            SlotIcon::QuestionMark => 2,
+            SlotIcon::Pizza => 3,
+            SlotIcon::Clawbot => 10,
+            SlotIcon::Robot => 11,
+            SlotIcon::PowerButton => 12,
+            SlotIcon::Planets => 13,
+            SlotIcon::Alien => 27,
+            SlotIcon::AlienInUfo => 29,
+            SlotIcon::CupInField => 50,
+            SlotIcon::CupAndBall => 51,
+            SlotIcon::Matlab => 901,
+            SlotIcon::Pros => 902,
+            SlotIcon::RobotMesh => 903,
+            SlotIcon::RobotMeshCpp => 911,
+            SlotIcon::RobotMeshBlockly => 912,
+            SlotIcon::RobotMeshFlowol => 913,
+            SlotIcon::RobotMeshJs => 914,
+            SlotIcon::RobotMeshPy => 915,
+            SlotIcon::CodeFile => 920,
+            SlotIcon::VexcodeBrackets => 921,
+            SlotIcon::VexcodeBlocks => 922,
+            SlotIcon::VexcodePython => 925,
+            SlotIcon::VexcodeCpp => 926,
+            SlotIcon::Other(v) => v,
+        }
+    }
+}
+
 pub struct Slot {
-    /// The number in the file icon: 'USER???x.bmp'.
-    pub icon_number: u16,
+    pub icon: SlotIcon,
     pub name_length: u8,
     pub name: DynamicVarLengthString,
 }
 impl Decode for Slot {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
-        let icon_number = u16::decode(&mut data)?;
+        let icon = u16::decode(&mut data)?.into();
         let name_length = u8::decode(&mut data)?;
-        let name = DynamicVarLengthString::decode_with_max_size(&mut data, (name_length - 1) as _)?;
+        // Empty slots have no name at all, not even a terminator.
+        let name = DynamicVarLengthString::decode_with_max_size(
+            &mut data,
+            name_length.saturating_sub(1) as _,
+        )
+        .map_err(|e| e.context("name", 3))?;
 
         Ok(Self {
-            icon_number,
+            icon,
             name_length,
             name,
         })
     }
 }
 
-pub type GetProgramInfoPacket = Cdc2CommandPacket<86, 28, GetProgramInfoPayload>;
-pub type GetProgramInfoReplyPacket = Cdc2ReplyPacket<86, 28, GetProgramInfoReplyPayload>;
+pub type GetProgramInfoPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetProgramInfo.id() },
+    GetProgramInfoPayload,
+>;
+pub type GetProgramInfoReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetProgramInfo.id() },
+    GetProgramInfoReplyPayload,
+>;
 
 #[derive(Debug, Clone)]
 pub struct GetProgramInfoPayload {
@@ -57,10 +178,20 @@ pub struct GetProgramInfoReplyPayload {
     pub requested_slot: u8,
 }
 
-pub type GetSlot1To4InfoPacket = Cdc2CommandPacket<86, 49, ()>;
-pub type GetSlot1To4InfoReplyPacket = Cdc2CommandPacket<86, 49, SlotInfoPayload>;
-pub type GetSlot5To8InfoPacket = Cdc2CommandPacket<86, 50, ()>;
-pub type GetSlot5To8InfoReplyPacket = Cdc2CommandPacket<86, 50, SlotInfoPayload>;
+pub type GetSlot1To4InfoPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetSlot1To4Info.id() }, ()>;
+pub type GetSlot1To4InfoReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetSlot1To4Info.id() },
+    SlotInfoPayload,
+>;
+pub type GetSlot5To8InfoPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetSlot5To8Info.id() }, ()>;
+pub type GetSlot5To8InfoReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetSlot5To8Info.id() },
+    SlotInfoPayload,
+>;
 
 pub struct SlotInfoPayload {
     /// Bit Mask.
@@ -75,8 +206,44 @@ impl Decode for SlotInfoPayload {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
         let flags = u8::decode(&mut data)?;
-        let slots = Array::decode_with_len(&mut data, 4)?;
+        let slots = Array::decode_with_len(&mut data, 4).map_err(|e| e.context("slots", 1))?;
 
         Ok(Self { flags, slots })
     }
 }
+impl SlotInfoPayload {
+    /// Returns true if the `index`th slot in this payload (from 0 to 3) has a program in it.
+    pub fn is_used(&self, index: usize) -> bool {
+        self.flags & (1 << index) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GetSlot1To4InfoReplyPacket, SlotIcon};
+    use crate::decode::{Decode, DecodeError};
+
+    #[test]
+    fn slot_icons() {
+        assert_eq!(SlotIcon::from(11), SlotIcon::Robot);
+        assert_eq!(SlotIcon::from(400), SlotIcon::Other(400));
+        assert_eq!(u16::from(SlotIcon::Pros), 902);
+        assert_eq!(SlotIcon::AlienInUfo.file_name(), "USER029x.bmp");
+    }
+
+    #[test]
+    fn error_context() {
+        // The first slot's name runs out before its terminator.
+        let Err(error) = GetSlot1To4InfoReplyPacket::decode([
+            0xAA, 0x55, 0x56, 0x0A, 0x31, 0x76, 0x01, 0x00, 0x00, 0x05, b'a', b'b', 0x00, 0x00,
+        ]) else {
+            panic!("Decoded a truncated name");
+        };
+
+        let DecodeError::Context { path, offset, .. } = &error else {
+            panic!("Expected context, found {error:?}");
+        };
+        assert_eq!(path, "payload.slots[0].name");
+        assert_eq!(*offset, 10);
+    }
+}
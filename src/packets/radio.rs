@@ -1,7 +1,8 @@
 use crate::encode::{Encode, EncodeError};
 
 use super::{
-    cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket},
+    cdc::CdcCommandId,
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
     Decode,
 };
 
@@ -34,8 +35,13 @@ impl Decode for RadioStatus {
     }
 }
 
-pub type GetRadioStatusPacket = Cdc2CommandPacket<86, 38, ()>;
-pub type GetRadioStatusReplyPacket = Cdc2ReplyPacket<86, 38, RadioStatus>;
+pub type GetRadioStatusPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetRadioStatus.id() }, ()>;
+pub type GetRadioStatusReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetRadioStatus.id() },
+    RadioStatus,
+>;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -54,8 +60,13 @@ impl Encode for RadioChannel {
         Ok(vec![*self as u8])
     }
 }
-pub type SelectRadioChannelPacket = Cdc2CommandPacket<86, 16, SelectRadioChannelPayload>;
-pub type SelectRadioChannelReplyPacket = Cdc2ReplyPacket<86, 16, ()>;
+pub type SelectRadioChannelPacket = Cdc2CommandPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::SelectRadioChannel.id() },
+    SelectRadioChannelPayload,
+>;
+pub type SelectRadioChannelReplyPacket =
+    Cdc2ReplyPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::SelectRadioChannel.id() }, ()>;
 
 #[derive(Debug, Clone)]
 pub struct SelectRadioChannelPayload {
@@ -1,6 +1,6 @@
 use super::{
-    cdc::{CdcCommandPacket, CdcReplyPacket},
-    cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket},
+    cdc::{CdcCommandId, CdcCommandPacket, CdcReplyPacket},
+    cdc2::{Cdc2CommandId, Cdc2CommandPacket, Cdc2ReplyPacket},
 };
 use crate::{
     decode::{Decode, DecodeError},
@@ -8,25 +8,38 @@ use crate::{
 };
 use bitflags::bitflags;
 
-#[repr(u16)]
-#[derive(Debug, Clone, Copy)]
+/// The kind of V5 device on the other end of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ProductType {
-    Brain = 0x10,
-    Controller = 0x11,
+    Brain,
+    Controller,
+    /// A product ID this crate doesn't know about, such as one added by newer firmware.
+    Unknown(u8),
+}
+impl From<u8> for ProductType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x10 => Self::Brain,
+            0x11 => Self::Controller,
+            v => Self::Unknown(v),
+        }
+    }
+}
+impl From<ProductType> for u8 {
+    fn from(value: ProductType) -> Self {
+        match value {
+            ProductType::Brain => 0x10,
+            ProductType::Controller => 0x11,
+            ProductType::Unknown(v) => v,
+        }
+    }
 }
 impl Decode for ProductType {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
         let _unknown = u8::decode(&mut data)?;
-        let val = u8::decode(data)?;
-        match val {
-            0x10 => Ok(Self::Brain),
-            0x11 => Ok(Self::Controller),
-            v => Err(DecodeError::UnexpectedValue {
-                value: v,
-                expected: &[0x10, 0x11],
-            }),
-        }
+        Ok(u8::decode(data)?.into())
     }
 }
 
@@ -41,23 +54,31 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// The bits of [`SystemFlags::flags`].
+    ///
+    /// The meaning of the bits that aren't listed is unknown. (RESEARCH NEEDED)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SystemFlagBits: u32 {
+        /// The radio is in data mode rather than match mode.
+        const RADIO_DATA_MODE = 1 << 20;
+        const BRAIN_BUTTON_DOUBLE_CLICKED = 1 << 18;
+        const BATTERY_CHARGING = 1 << 17;
+        const BRAIN_BUTTON_CLICKED = 1 << 15;
+        const VEXNET_MODE = 1 << 14;
+        const PARTNER_CONTROLLER = 1 << 13;
+        const RADIO_CONNECTED = 1 << 10;
+        const RADIO_AVAILABLE = 1 << 9;
+        const CONTROLLER_TETHERED = 1 << 8;
+        const PAGE_CHANGED = 1 << 2;
+        /// A device was plugged into or unplugged from a smart port.
+        const DEVICE_CHANGED = 1 << 0;
+    }
+}
+
 pub struct SystemFlags {
-    /// Bit mask.
-    /// From left to right:
-    /// no.1 to no.8 bit - Page index
-    /// no.12 bit = Radio Data mode on
-    /// no.14 bit = Brain button double clicked
-    /// no.15 bit = Battery is charging
-    /// no.17 bit = Brain button clicked
-    /// no.18 bit = Is VexNet mode
-    /// no.19 bit = Has partner controller
-    /// no.22 bit = Radio connected
-    /// no.23 bit = Radio available
-    /// no.24 bit = Controller tethered
-    /// no.30 bit = Page changed
-    /// no.32 bit = Device added/removed
-    /// (RESEARCH NEEDED)
-    pub flags: u32,
+    /// The top 8 bits are the index of the page the brain's screen is on.
+    pub flags: SystemFlagBits,
 
     /// Battery percent = First four bits * 8
     /// Controller battery percent = Last four bits * 8
@@ -72,10 +93,36 @@ pub struct SystemFlags {
     /// 145 = Driver program
     pub current_program: u8,
 }
+impl SystemFlags {
+    /// The index of the page the brain's screen is on.
+    pub fn page_index(&self) -> u8 {
+        (self.flags.bits() >> 24) as u8
+    }
+
+    /// The brain's battery level, from 0 to 100.
+    pub fn battery_percent(&self) -> u8 {
+        nibble_percent(self.byte_1 >> 4)
+    }
+
+    /// The battery level of the controller connected to the brain, from 0 to 100.
+    pub fn controller_battery_percent(&self) -> u8 {
+        nibble_percent(self.byte_1 & 0x0F)
+    }
+
+    /// The battery level of the partner controller, from 0 to 100.
+    pub fn partner_controller_battery_percent(&self) -> u8 {
+        nibble_percent(self.byte_2 & 0x0F)
+    }
+}
+
+/// Battery levels are sent in steps of 8%, so a full battery would read as 120%.
+fn nibble_percent(nibble: u8) -> u8 {
+    (nibble * 8).min(100)
+}
 impl Decode for SystemFlags {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
-        let flags = u32::decode(&mut data)?;
+        let flags = SystemFlagBits::from_bits_retain(u32::decode(&mut data)?);
         let byte_1 = u8::decode(&mut data)?;
         let byte_2 = u8::decode(&mut data)?;
         let current_program = u8::decode(&mut data)?;
@@ -130,7 +177,18 @@ impl Decode for SystemStatus {
     }
 }
 
+/// The part of a match that a brain's programs are allowed to run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompetitionMode {
+    Disabled,
+    Autonomous,
+    /// Driver control, which is also the mode with no field controller connected.
+    Driver,
+}
+
 pub struct SystemDetails {
+    /// The brain's unique ID.
     pub unique_id: u32,
 
     /// (RESEARCH NEEDED)
@@ -159,6 +217,23 @@ pub struct SystemDetails {
     pub golden_version: Version,
     pub nxp_version: Option<Version>,
 }
+impl SystemDetails {
+    /// Which part of a match the brain is in, as set by a competition switch or field controller.
+    pub fn competition_mode(&self) -> CompetitionMode {
+        if self.flags_2 & (1 << 13) != 0 {
+            CompetitionMode::Disabled
+        } else if self.flags_2 & (1 << 14) != 0 {
+            CompetitionMode::Autonomous
+        } else {
+            CompetitionMode::Driver
+        }
+    }
+
+    /// Whether a competition switch or field controller is plugged into the controller.
+    pub fn field_controller_connected(&self) -> bool {
+        self.flags_2 & (1 << 12) != 0
+    }
+}
 impl Decode for SystemDetails {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
@@ -183,14 +258,25 @@ impl Decode for SystemDetails {
     }
 }
 
-pub type GetSystemFlagsPacket = Cdc2CommandPacket<86, 32, ()>;
-pub type GetSystemFlagsReplyPacket = Cdc2ReplyPacket<86, 32, SystemFlags>;
+pub type GetSystemFlagsPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetSystemFlags.id() }, ()>;
+pub type GetSystemFlagsReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetSystemFlags.id() },
+    SystemFlags,
+>;
 
-pub type GetSystemStatusPacket = Cdc2CommandPacket<86, 34, ()>;
-pub type GetSystemStatusReplyPacket = Cdc2ReplyPacket<86, 34, SystemStatus>;
+pub type GetSystemStatusPacket =
+    Cdc2CommandPacket<{ CdcCommandId::Cdc2.id() }, { Cdc2CommandId::GetSystemStatus.id() }, ()>;
+pub type GetSystemStatusReplyPacket = Cdc2ReplyPacket<
+    { CdcCommandId::Cdc2.id() },
+    { Cdc2CommandId::GetSystemStatus.id() },
+    SystemStatus,
+>;
 
-pub type GetSystemVersionPacket = CdcCommandPacket<164, ()>;
-pub type GetSystemVersionReplyPacket = CdcReplyPacket<164, GetSystemVersionReplyPayload>;
+pub type GetSystemVersionPacket = CdcCommandPacket<{ CdcCommandId::GetSystemVersion.id() }, ()>;
+pub type GetSystemVersionReplyPacket =
+    CdcReplyPacket<{ CdcCommandId::GetSystemVersion.id() }, GetSystemVersionReplyPayload>;
 
 #[derive(Debug)]
 pub struct GetSystemVersionReplyPayload {
@@ -213,8 +299,8 @@ impl Decode for GetSystemVersionReplyPayload {
     }
 }
 
-pub type Query1Packet = CdcCommandPacket<33, ()>;
-pub type Query1ReplyPacket = CdcReplyPacket<33, Query1ReplyPayload>;
+pub type Query1Packet = CdcCommandPacket<{ CdcCommandId::Query1.id() }, ()>;
+pub type Query1ReplyPacket = CdcReplyPacket<{ CdcCommandId::Query1.id() }, Query1ReplyPayload>;
 
 pub struct Query1ReplyPayload {
     pub unknown_1: [u8; 4],
@@ -228,3 +314,82 @@ pub struct Query1ReplyPayload {
     pub bootload_flag_1: u8,
     pub bootload_flag_2: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CompetitionMode, GetSystemVersionReplyPacket, ProductType, SystemDetails, SystemFlagBits,
+        SystemFlags,
+    };
+    use crate::decode::{Decode, DecodeError};
+    use crate::fixtures::system_version_reply;
+    use crate::version::Version;
+
+    #[test]
+    fn product_type() {
+        assert_eq!(
+            ProductType::decode([0x00, 0x11]).unwrap(),
+            ProductType::Controller
+        );
+        assert_eq!(
+            ProductType::decode([0x00, 0x20]).unwrap(),
+            ProductType::Unknown(0x20)
+        );
+        assert_eq!(u8::from(ProductType::Unknown(0x20)), 0x20);
+    }
+
+    #[test]
+    fn battery_percent() {
+        let flags = SystemFlags {
+            flags: SystemFlagBits::from_bits_retain(0x0300_0000),
+            byte_1: 0xF6,
+            byte_2: 0x03,
+            current_program: 0,
+        };
+
+        assert_eq!(flags.battery_percent(), 100);
+        assert_eq!(flags.controller_battery_percent(), 48);
+        assert_eq!(flags.partner_controller_battery_percent(), 24);
+        assert_eq!(flags.page_index(), 3);
+    }
+
+    #[test]
+    fn competition_mode() {
+        let mut details = SystemDetails {
+            unique_id: 0,
+            flags_1: 0,
+            flags_2: 0,
+            flags_3: 0,
+            unknown: 0,
+            golden_version: Version {
+                major: 1,
+                minor: 0,
+                build: 0,
+                beta: 0,
+            },
+            nxp_version: None,
+        };
+        assert_eq!(details.competition_mode(), CompetitionMode::Driver);
+
+        details.flags_2 = 0x5000;
+        assert_eq!(details.competition_mode(), CompetitionMode::Autonomous);
+        assert!(details.field_controller_connected());
+
+        // Disabled wins over autonomous.
+        details.flags_2 = 0x6000;
+        assert_eq!(details.competition_mode(), CompetitionMode::Disabled);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(matches!(
+            ProductType::decode([0x00]),
+            Err(DecodeError::PacketTooShort)
+        ));
+
+        let packet = system_version_reply(1);
+        for len in 0..packet.len() {
+            assert!(GetSystemVersionReplyPacket::decode(packet[..len].to_vec()).is_err());
+        }
+    }
+}
@@ -10,7 +10,10 @@ pub struct DynamicVarLengthString(pub String, pub usize);
 impl DynamicVarLengthString {
     pub fn new(string: String, max_size: usize) -> Result<Self, EncodeError> {
         if string.len() > max_size {
-            return Err(EncodeError::StringTooLong);
+            return Err(EncodeError::StringTooLong {
+                len: string.len(),
+                max: max_size,
+            });
         }
 
         Ok(Self(string, max_size))
@@ -44,73 +47,97 @@ impl DynamicVarLengthString {
     }
 }
 
+/// A null-terminated string of variable length.
+///
+/// `MAX_LEN` is a budget in UTF-8 bytes, not characters, and does not include the null terminator.
+/// Non-ASCII strings will therefore fit fewer than `MAX_LEN` characters.
+/// Once encoded, the size will be at most `MAX_LEN + 1` bytes.
 #[derive(Debug, Clone)]
 pub struct VarLengthString<const MAX_LEN: usize>(pub String);
 impl<const MAX_LEN: usize> VarLengthString<MAX_LEN> {
+    /// Creates a new variable length string.
+    /// # Errors
+    /// Returns [`EncodeError::StringTooLong`] if the string is more than `MAX_LEN` bytes long.
     pub fn new(string: String) -> Result<Self, EncodeError> {
-        if string.as_bytes().len() > MAX_LEN {
-            return Err(EncodeError::StringTooLong);
+        // String::len is the length in bytes
+        if string.len() > MAX_LEN {
+            return Err(EncodeError::StringTooLong {
+                len: string.len(),
+                max: MAX_LEN,
+            });
         }
 
         Ok(Self(string))
     }
 }
 impl<const MAX_LEN: usize> Encode for VarLengthString<MAX_LEN> {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        let mut bytes = self.0.as_bytes().to_vec();
-        bytes.push(0);
-        Ok(bytes)
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        // The inner string is public, so it may not have gone through `new`.
+        if self.0.len() > MAX_LEN {
+            return Err(EncodeError::StringTooLong {
+                len: self.0.len(),
+                max: MAX_LEN,
+            });
+        }
+
+        buf.extend_from_slice(self.0.as_bytes());
+        buf.push(0);
+        Ok(())
     }
 }
 impl<const MAX_LEN: usize> Decode for VarLengthString<MAX_LEN> {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
 
-        let mut string_bytes = [0u8; MAX_LEN];
-        for (i, string_byte) in string_bytes.iter_mut().enumerate() {
+        let mut string_bytes = Vec::new();
+        loop {
             let byte = u8::decode(&mut data)?;
-            if i == MAX_LEN {
-                if byte != 0 {
-                    return Err(DecodeError::UnterminatedString);
-                }
-                break;
-            }
             if byte == 0 {
                 break;
             }
+            if string_bytes.len() == MAX_LEN {
+                return Err(DecodeError::UnterminatedString);
+            }
 
-            *string_byte = byte;
+            string_bytes.push(byte);
         }
 
-        Ok(Self(String::from_utf8(string_bytes.to_vec())?))
+        Ok(Self(String::from_utf8(string_bytes)?))
     }
 }
 /// A null-terminated fixed length string.
+///
+/// Like [`VarLengthString`], `LEN` is measured in UTF-8 bytes.
 /// Once encoded, the size will be `LEN + 1` bytes.
 #[derive(Debug, Clone)]
 pub struct FixedLengthString<const LEN: usize>(String);
 impl<const LEN: usize> FixedLengthString<LEN> {
     pub fn new(string: String) -> Result<Self, EncodeError> {
-        if string.as_bytes().len() > LEN {
-            return Err(EncodeError::StringTooLong);
+        // String::len is the length in bytes
+        if string.len() > LEN {
+            return Err(EncodeError::StringTooLong {
+                len: string.len(),
+                max: LEN,
+            });
         }
 
         Ok(Self(string))
     }
 }
 impl<const LEN: usize> Encode for FixedLengthString<LEN> {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
-        let mut encoded = [0u8; LEN];
-
-        let string_bytes = self.0.clone().into_bytes();
-        if string_bytes.len() > encoded.len() {
-            return Err(EncodeError::StringTooLong);
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let string_bytes = self.0.as_bytes();
+        if string_bytes.len() > LEN {
+            return Err(EncodeError::StringTooLong {
+                len: string_bytes.len(),
+                max: LEN,
+            });
         }
 
-        encoded[..string_bytes.len()].copy_from_slice(&string_bytes);
-        let mut encoded = encoded.to_vec();
-        encoded.push(0);
-        Ok(encoded)
+        buf.extend_from_slice(string_bytes);
+        // Pad out to the fixed length, plus the null terminator.
+        buf.resize(buf.len() + (LEN - string_bytes.len()) + 1, 0);
+        Ok(())
     }
 }
 impl<const LEN: usize> Decode for FixedLengthString<LEN> {
@@ -122,7 +149,9 @@ impl<const LEN: usize> Decode for FixedLengthString<LEN> {
         if terminator != 0 {
             Err(DecodeError::UnterminatedString)
         } else {
-            Ok(Self(String::from_utf8(string_bytes.to_vec())?))
+            // Strip the padding after the end of the string
+            let len = string_bytes.iter().position(|&b| b == 0).unwrap_or(LEN);
+            Ok(Self(String::from_utf8(string_bytes[..len].to_vec())?))
         }
     }
 }
@@ -132,11 +161,153 @@ impl<const LEN: usize> Display for FixedLengthString<LEN> {
     }
 }
 
+/// A fixed length string with no null terminator.
+///
+/// `LEN` is measured in UTF-8 bytes. Strings shorter than `LEN` are padded with zeroes.
+/// Once encoded, the size will be exactly `LEN` bytes.
+#[derive(Debug, Clone)]
+pub struct UnterminatedFixedLengthString<const LEN: usize>(String);
+impl<const LEN: usize> UnterminatedFixedLengthString<LEN> {
+    pub fn new(string: String) -> Result<Self, EncodeError> {
+        // String::len is the length in bytes
+        if string.len() > LEN {
+            return Err(EncodeError::StringTooLong {
+                len: string.len(),
+                max: LEN,
+            });
+        }
+
+        Ok(Self(string))
+    }
+}
+impl<const LEN: usize> Encode for UnterminatedFixedLengthString<LEN> {
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let string_bytes = self.0.as_bytes();
+
+        buf.extend_from_slice(string_bytes);
+        buf.resize(buf.len() + (LEN - string_bytes.len()), 0);
+        Ok(())
+    }
+}
+impl<const LEN: usize> Decode for UnterminatedFixedLengthString<LEN> {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        let string_bytes: [u8; LEN] = Decode::decode(data)?;
+
+        // Strip the padding after the end of the string
+        let len = string_bytes.iter().position(|&b| b == 0).unwrap_or(LEN);
+        Ok(Self(String::from_utf8(string_bytes[..len].to_vec())?))
+    }
+}
+impl<const LEN: usize> Display for UnterminatedFixedLengthString<LEN> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Gives each string wrapper the same ways of getting at the string inside.
+macro_rules! string_accessors {
+    ($($name:ident),*) => {$(
+        impl<const LEN: usize> $name<LEN> {
+            /// The string, without any padding or terminator.
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn into_inner(self) -> String {
+                self.0
+            }
+        }
+        impl<const LEN: usize> AsRef<str> for $name<LEN> {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+        impl<const LEN: usize> From<$name<LEN>> for String {
+            fn from(string: $name<LEN>) -> Self {
+                string.0
+            }
+        }
+    )*};
+}
+string_accessors!(
+    VarLengthString,
+    FixedLengthString,
+    UnterminatedFixedLengthString
+);
+
+/// Converts between string wrappers, failing with [`EncodeError::StringTooLong`] if the
+/// string doesn't fit in the new length.
+macro_rules! string_conversions {
+    ($($from:ident => $to:ident),*) => {$(
+        impl<const FROM: usize, const TO: usize> TryFrom<$from<FROM>> for $to<TO> {
+            type Error = EncodeError;
+
+            fn try_from(string: $from<FROM>) -> Result<Self, Self::Error> {
+                Self::new(string.0)
+            }
+        }
+    )*};
+}
+string_conversions!(
+    VarLengthString => FixedLengthString,
+    VarLengthString => UnterminatedFixedLengthString,
+    FixedLengthString => VarLengthString,
+    FixedLengthString => UnterminatedFixedLengthString,
+    UnterminatedFixedLengthString => VarLengthString,
+    UnterminatedFixedLengthString => FixedLengthString
+);
+
 #[cfg(test)]
 mod tests {
-    use crate::{decode::Decode, encode::Encode};
+    use proptest::prelude::*;
 
-    use super::FixedLengthString;
+    use crate::{
+        decode::{Decode, DecodeError},
+        encode::{Encode, EncodeError},
+    };
+
+    use super::{FixedLengthString, UnterminatedFixedLengthString, VarLengthString};
+
+    // No character takes more than 4 bytes, so these always fit in the byte budgets below.
+    proptest! {
+        #[test]
+        fn var_length_roundtrip(string in "\\PC{0,8}") {
+            let encoded = VarLengthString::<32>::new(string.clone()).unwrap().encode().unwrap();
+            prop_assert_eq!(encoded.len(), string.len() + 1);
+            prop_assert_eq!(VarLengthString::<32>::decode(encoded).unwrap().0, string);
+        }
+
+        #[test]
+        fn fixed_length_roundtrip(string in "\\PC{0,5}") {
+            let encoded = FixedLengthString::<23>::new(string.clone()).unwrap().encode().unwrap();
+            prop_assert_eq!(encoded.len(), 24);
+            prop_assert_eq!(FixedLengthString::<23>::decode(encoded).unwrap().0, string);
+        }
+
+        #[test]
+        fn unterminated_fixed_length_roundtrip(string in "[ -~]{0,8}") {
+            let encoded = UnterminatedFixedLengthString::<8>::new(string.clone())
+                .unwrap()
+                .encode()
+                .unwrap();
+            prop_assert_eq!(encoded.len(), 8);
+            prop_assert_eq!(UnterminatedFixedLengthString::<8>::decode(encoded).unwrap().0, string);
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let name = FixedLengthString::<23>::new("slot_1.bin".to_string()).unwrap();
+        let var: VarLengthString<10> = name.clone().try_into().unwrap();
+        assert_eq!(var.as_str(), "slot_1.bin");
+
+        // Strings are never cut short to fit.
+        let short: Result<UnterminatedFixedLengthString<8>, _> = name.try_into();
+        assert!(matches!(
+            short,
+            Err(EncodeError::StringTooLong { len: 10, max: 8 })
+        ));
+    }
 
     #[test]
     #[should_panic]
@@ -156,4 +327,52 @@ mod tests {
 
         assert_eq!(decoded_string.0, "helloworld".to_string());
     }
+    #[test]
+    fn padded_fixed_length_string() {
+        let bytes = b"hello\0\0\0\0\0\0".to_vec();
+        let decoded_string = FixedLengthString::<10>::decode(bytes).unwrap();
+
+        assert_eq!(decoded_string.0, "hello".to_string());
+    }
+    #[test]
+    fn var_length_string_byte_budget() {
+        // 5 characters, but 10 bytes of UTF-8
+        let name = "ééééé".to_string();
+        assert_eq!(name.chars().count(), 5);
+        assert!(matches!(
+            VarLengthString::<8>::new(name.clone()),
+            Err(EncodeError::StringTooLong { len: 10, max: 8 })
+        ));
+        assert!(VarLengthString::<10>::new(name).is_ok());
+    }
+    #[test]
+    fn var_length_string() {
+        let string = VarLengthString::<16>::new("crème".to_string()).unwrap();
+        let encoded = string.encode().unwrap();
+        assert_eq!(encoded, b"cr\xc3\xa8me\0".to_vec());
+
+        // Bytes after the terminator should not be consumed.
+        let mut data = encoded.into_iter().chain([0xAB]);
+        let decoded = VarLengthString::<16>::decode(&mut data).unwrap();
+        assert_eq!(decoded.0, "crème");
+        assert_eq!(data.collect::<Vec<_>>(), vec![0xAB]);
+    }
+    #[test]
+    fn unterminated_var_length_string() {
+        let result = VarLengthString::<4>::decode(b"hello\0".to_vec());
+        assert!(matches!(result, Err(DecodeError::UnterminatedString)));
+    }
+    #[test]
+    fn unterminated_fixed_length_string() {
+        let string = UnterminatedFixedLengthString::<8>::new("hello".to_string()).unwrap();
+        let encoded = string.encode().unwrap();
+        assert_eq!(encoded, b"hello\0\0\0".to_vec());
+
+        let decoded = UnterminatedFixedLengthString::<8>::decode(encoded).unwrap();
+        assert_eq!(decoded.0, "hello");
+
+        // A full length string has no padding at all.
+        let full = UnterminatedFixedLengthString::<5>::new("hello".to_string()).unwrap();
+        assert_eq!(full.encode().unwrap(), b"hello".to_vec());
+    }
 }
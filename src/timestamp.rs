@@ -1,12 +1,73 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 /// The epoch of the serial protocols timestamps
 pub const J2000_EPOCH: u32 = 946684800;
 
+/// The current time as the number of seconds since [`J2000_EPOCH`].
+///
+/// The brain has no packet for setting its clock. Instead, it stamps files with the
+/// timestamp sent when they are uploaded, so this should be the host's current time.
 pub fn j2000_timestamp() -> i32 {
-    (SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis()
-        - J2000_EPOCH as u128) as i32
+    j2000_timestamp_from(SystemTime::now())
+}
+
+/// Converts a point in time to the number of seconds since [`J2000_EPOCH`].
+///
+/// Timestamps only fit times from late 1931 to early 2068. Times outside of that are clamped
+/// to the earliest or latest timestamp.
+pub fn j2000_timestamp_from(time: SystemTime) -> i32 {
+    let seconds = match time.duration_since(j2000()) {
+        Ok(since) => i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+        Err(before) => -i64::try_from(before.duration().as_secs()).unwrap_or(i64::MAX),
+    };
+    seconds.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+/// Converts a number of seconds since [`J2000_EPOCH`] to a point in time.
+pub fn system_time_from_j2000(timestamp: i32) -> SystemTime {
+    let j2000 = j2000();
+    let offset = Duration::from_secs(timestamp.unsigned_abs() as u64);
+    if timestamp < 0 {
+        j2000 - offset
+    } else {
+        j2000 + offset
+    }
+}
+
+fn j2000() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(J2000_EPOCH as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{j2000_timestamp_from, system_time_from_j2000, J2000_EPOCH};
+
+    #[test]
+    fn seconds_since_j2000() {
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(J2000_EPOCH as u64);
+        assert_eq!(j2000_timestamp_from(epoch), 0);
+        assert_eq!(j2000_timestamp_from(epoch + Duration::from_millis(1500)), 1);
+        assert_eq!(
+            j2000_timestamp_from(SystemTime::UNIX_EPOCH),
+            -(J2000_EPOCH as i32)
+        );
+
+        // Out of range times are clamped rather than wrapping around.
+        let far_future = epoch + Duration::from_secs(u32::MAX as u64);
+        assert_eq!(j2000_timestamp_from(far_future), i32::MAX);
+        let far_past = epoch - Duration::from_secs(u32::MAX as u64);
+        assert_eq!(j2000_timestamp_from(far_past), i32::MIN);
+    }
+
+    #[test]
+    fn j2000_roundtrip() {
+        for timestamp in [0, 1, -1, 779_000_000] {
+            assert_eq!(
+                j2000_timestamp_from(system_time_from_j2000(timestamp)),
+                timestamp
+            );
+        }
+    }
 }
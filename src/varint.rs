@@ -6,14 +6,23 @@ use crate::encode::{Encode, EncodeError};
 #[derive(Debug, Clone, Copy)]
 pub struct VarU16(u16);
 impl VarU16 {
+    /// The largest value that can be encoded, which is also the longest a packet's payload can be.
+    pub const MAX: u16 = u16::MAX >> 1;
+
     /// Creates a new variable length u16.
     /// # Panics
     /// Panics if the value is too large to be encoded as a variable length u16.
     pub fn new(val: u16) -> Self {
-        if val > (u16::MAX >> 1) {
-            panic!("Value too large for variable length u16");
+        Self::try_new(val).expect("Value too large for variable length u16")
+    }
+    /// Creates a new variable length u16.
+    /// # Errors
+    /// Returns [`EncodeError::VarShortTooLarge`] if the value is too large to be encoded as a variable length u16.
+    pub fn try_new(val: u16) -> Result<Self, EncodeError> {
+        if val > Self::MAX {
+            return Err(EncodeError::VarShortTooLarge);
         }
-        Self(val)
+        Ok(Self(val))
     }
     pub fn into_inner(self) -> u16 {
         self.0
@@ -23,8 +32,25 @@ impl VarU16 {
         first > (u8::MAX >> 1) as _
     }
 }
+/// The size of a payload `len` bytes long, to be written in front of it.
+pub(crate) fn payload_size(len: usize) -> Result<VarU16, EncodeError> {
+    u16::try_from(len)
+        .ok()
+        .and_then(|len| VarU16::try_new(len).ok())
+        .ok_or(EncodeError::PayloadTooLarge {
+            len,
+            max: VarU16::MAX as usize,
+        })
+}
+impl TryFrom<u16> for VarU16 {
+    type Error = EncodeError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Self::try_new(value)
+    }
+}
 impl Encode for VarU16 {
-    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+    fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
         if self.0 > (u16::MAX >> 1) {
             return Err(EncodeError::VarShortTooLarge);
         }
@@ -32,11 +58,12 @@ impl Encode for VarU16 {
         if self.0 > (u8::MAX >> 1) as _ {
             let first = (self.0 >> 8) as u8 | 0x80;
             let last = (self.0 & u8::MAX as u16) as u8;
-            Ok([first, last].to_vec())
+            buf.extend([first, last]);
         } else {
-            let val = self.0 as u8;
-            Ok(vec![val])
+            buf.push(self.0 as u8);
         }
+
+        Ok(())
     }
 }
 impl Decode for VarU16 {
@@ -57,7 +84,26 @@ impl Decode for VarU16 {
 
 #[cfg(test)]
 mod tests {
-    use crate::{decode::Decode, encode::Encode, varint::VarU16};
+    use proptest::prelude::*;
+
+    use crate::{
+        decode::{Decode, DecodeError},
+        encode::{Encode, EncodeError},
+        varint::VarU16,
+    };
+
+    proptest! {
+        #[test]
+        fn roundtrip(value in 0u16..=0x7FFF) {
+            let encoded = VarU16::new(value).encode().unwrap();
+            // Values that fit in 7 bits take a single byte.
+            prop_assert_eq!(encoded.len(), if value > 0x7F { 2 } else { 1 });
+
+            let mut data = encoded.into_iter().chain([0xFF]);
+            prop_assert_eq!(VarU16::decode(&mut data).unwrap().into_inner(), value);
+            prop_assert_eq!(data.next(), Some(0xFF));
+        }
+    }
 
     #[test]
     fn wide() {
@@ -80,4 +126,33 @@ mod tests {
         assert_eq!(ENCODED.to_vec(), var.encode().unwrap());
         assert_eq!(VAL, VarU16::decode(ENCODED).unwrap().into_inner())
     }
+
+    #[test]
+    fn too_large() {
+        assert!(matches!(
+            VarU16::try_new(u16::MAX),
+            Err(EncodeError::VarShortTooLarge)
+        ));
+        assert!(VarU16::try_from(u16::MAX >> 1).is_ok());
+    }
+
+    #[test]
+    fn truncated_wide() {
+        // The high bit is set, so a second byte is expected.
+        let result = VarU16::decode([0x8f]);
+        assert!(matches!(result, Err(DecodeError::PacketTooShort)));
+    }
+
+    #[test]
+    fn host_bound_size() {
+        // Header, command ID, wide payload size and the start of the payload.
+        let packet = [0xAA, 0x55, 0x56, 0x81, 0x02, 0x13, 0x76];
+        let mut data = packet.into_iter().skip(3);
+
+        let size = VarU16::decode(&mut data).unwrap();
+        assert_eq!(size.into_inner(), 0x102);
+
+        // Only the size bytes should be consumed
+        assert_eq!(data.collect::<Vec<_>>(), vec![0x13, 0x76]);
+    }
 }
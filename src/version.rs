@@ -1,13 +1,29 @@
+use std::fmt::{self, Display};
+
 use crate::decode::{Decode, DecodeError};
 use crate::encode::{Encode, EncodeError};
 
-#[derive(Debug, Clone, Copy)]
+/// A firmware or file version, sent over the wire as four bytes.
+///
+/// Versions are ordered by `major`, then `minor`, then `build`, then `beta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
     pub build: u8,
     pub beta: u8,
 }
+impl Display for Version {
+    /// Formats the version as `major.minor.build.beta`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.build, self.beta
+        )
+    }
+}
 impl Encode for Version {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         Ok(vec![self.major, self.minor, self.build, self.beta])
@@ -28,3 +44,55 @@ impl Decode for Version {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::Version;
+    use crate::{decode::Decode, encode::Encode};
+
+    proptest! {
+        #[test]
+        fn roundtrip_any(bytes in any::<[u8; 4]>()) {
+            let version = Version::decode(bytes).unwrap();
+            prop_assert_eq!(version.encode().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let version = Version {
+            major: 1,
+            minor: 2,
+            build: 3,
+            beta: 4,
+        };
+        let encoded = version.encode().unwrap();
+        assert_eq!(encoded, [1, 2, 3, 4]);
+
+        // Exactly four bytes are consumed.
+        let mut data = encoded.into_iter().chain([0xFF]);
+        assert_eq!(Version::decode(&mut data).unwrap(), version);
+        assert_eq!(data.next(), Some(0xFF));
+    }
+
+    #[test]
+    fn display_and_order() {
+        let version = Version {
+            major: 1,
+            minor: 2,
+            build: 3,
+            beta: 0,
+        };
+        assert_eq!(version.to_string(), "1.2.3.0");
+
+        let newer = Version {
+            minor: 10,
+            build: 0,
+            ..version
+        };
+        assert!(newer > version);
+        assert!(Version { beta: 1, ..version } > version);
+    }
+}
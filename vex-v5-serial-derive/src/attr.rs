@@ -0,0 +1,37 @@
+use syn::{Field, Ident, LitStr, Result};
+
+/// Options parsed from `#[vex(...)]` attributes on a field.
+#[derive(Default)]
+pub struct FieldOptions {
+    /// The field is not part of the wire format.
+    pub skip: bool,
+    /// The number of elements in a `Vec` field is read from a previously decoded field.
+    pub len: Option<Ident>,
+}
+
+impl FieldOptions {
+    pub fn parse(field: &Field) -> Result<Self> {
+        let mut options = Self::default();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("vex"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    options.skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("len") {
+                    let field: LitStr = meta.value()?.parse()?;
+                    options.len = Some(field.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown vex attribute"))
+                }
+            })?;
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,99 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, Result};
+
+use crate::attr::FieldOptions;
+
+pub fn derive(mut input: DeriveInput) -> Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Decode can only be derived for structs",
+        ));
+    };
+
+    let mut locals = Vec::new();
+    let mut statements = Vec::new();
+    for (index, field) in data.fields.iter().enumerate() {
+        let options = FieldOptions::parse(field)?;
+        let ty = &field.ty;
+        // Prefixed so that fields can't shadow the iterator or each other.
+        let local = match &field.ident {
+            Some(ident) => format_ident!("__field_{}", ident),
+            None => format_ident!("__field_{}", index),
+        };
+        // Named in errors from decoding the field.
+        let path = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None => index.to_string(),
+        };
+
+        statements.push(if options.skip {
+            quote! {
+                let #local: #ty = ::core::default::Default::default();
+            }
+        } else if let Some(len) = options.len {
+            let len_local = format_ident!("__field_{}", len);
+            if !locals.contains(&len_local) {
+                return Err(Error::new_spanned(
+                    &len,
+                    "length field must be declared before this field",
+                ));
+            }
+            quote! {
+                let __start = __data.position();
+                let #local: #ty = ::vex_v5_serial::array::Array::decode_with_len(
+                    &mut __data,
+                    #len_local as usize,
+                )
+                .map_err(|e| e.context(#path, __start))?
+                .into_inner();
+            }
+        } else {
+            quote! {
+                let __start = __data.position();
+                let #local = <#ty as ::vex_v5_serial::decode::Decode>::decode(&mut __data)
+                    .map_err(|e| e.context(#path, __start))?;
+            }
+        });
+        locals.push(local);
+    }
+
+    let construct = match &data.fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!(Self { #(#names: #locals),* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#locals),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    // Every generic type must itself be decodable.
+    let type_params = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = input.generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(parse_quote!(#param: ::vex_v5_serial::decode::Decode));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::vex_v5_serial::decode::Decode for #name #ty_generics #where_clause {
+            #[allow(unused_mut, unused_variables)]
+            fn decode(
+                data: impl ::core::iter::IntoIterator<Item = u8>,
+            ) -> ::core::result::Result<Self, ::vex_v5_serial::decode::DecodeError> {
+                let mut __data = ::vex_v5_serial::decode::Counted::new(data.into_iter());
+                #(#statements)*
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+    })
+}
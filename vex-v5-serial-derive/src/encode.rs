@@ -0,0 +1,54 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Error, Index, Member, Result};
+
+use crate::attr::FieldOptions;
+
+pub fn derive(mut input: DeriveInput) -> Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Encode can only be derived for structs",
+        ));
+    };
+
+    let mut members = Vec::new();
+    for (index, field) in data.fields.iter().enumerate() {
+        if FieldOptions::parse(field)?.skip {
+            continue;
+        }
+
+        members.push(match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(index)),
+        });
+    }
+
+    // Every generic type must itself be encodable.
+    let type_params = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = input.generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(parse_quote!(#param: ::vex_v5_serial::encode::Encode));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::vex_v5_serial::encode::Encode for #name #ty_generics #where_clause {
+            fn encode_into(
+                &self,
+                buf: &mut ::std::vec::Vec<u8>,
+            ) -> ::core::result::Result<(), ::vex_v5_serial::encode::EncodeError> {
+                #(::vex_v5_serial::encode::Encode::encode_into(&self.#members, buf)?;)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
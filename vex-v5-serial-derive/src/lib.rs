@@ -0,0 +1,38 @@
+//! Derive macros for the `Encode` and `Decode` traits in `vex-v5-serial`.
+//!
+//! These are re-exported by `vex-v5-serial` when its `derive` feature is enabled.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod attr;
+mod decode;
+mod encode;
+
+/// Derives `Encode` for a struct by encoding each field in declaration order.
+///
+/// Fields marked with `#[vex(skip)]` are not encoded.
+#[proc_macro_derive(Encode, attributes(vex))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    encode::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `Decode` for a struct by decoding each field in declaration order.
+///
+/// Fields marked with `#[vex(skip)]` are not decoded and are set to their [`Default`] value.
+/// A `Vec` field marked with `#[vex(len = "field")]` decodes as many elements as the
+/// previously decoded `field` holds. Errors from decoding a field are wrapped in
+/// `DecodeError::Context`, naming the field and the byte it started at.
+///
+/// Like every `Decode` implementation, the derived one consumes bytes from the iterator it is
+/// given, so passing `&mut iter` leaves `iter` positioned after the decoded struct.
+#[proc_macro_derive(Decode, attributes(vex))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    decode::derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}